@@ -0,0 +1,47 @@
+/// With the `embedded-data` feature, compresses the dictionary and the templates into `OUT_DIR`
+/// for `src/embedded.rs` to include. They're read from `data/` unless `DESCRAMBLE_EMBED_DIR`
+/// points somewhere else. Without a generated `data/data.json`, the small `data/default.json`
+/// shipped with the repository is embedded instead.
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "embedded-data")]
+    embed();
+}
+
+#[cfg(feature = "embedded-data")]
+fn embed() {
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::env::var_os;
+    use std::fs::{File, read};
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    println!("cargo:rerun-if-env-changed=DESCRAMBLE_EMBED_DIR");
+    let embed_dir: Option<PathBuf> = var_os("DESCRAMBLE_EMBED_DIR").map(PathBuf::from);
+    let data_dir: PathBuf = embed_dir.clone().unwrap_or_else(|| PathBuf::from("data"));
+    let out_dir: PathBuf = PathBuf::from(var_os("OUT_DIR").unwrap());
+    for file in ["data.json", "templates.json"] {
+        let mut source: PathBuf = data_dir.join(file);
+        println!("cargo:rerun-if-changed={}", source.display());
+        if file == "data.json" && embed_dir.is_none() && !source.exists() {
+            source = data_dir.join("default.json");
+            println!("cargo:rerun-if-changed={}", source.display());
+        }
+        let bytes: Vec<u8> = match read(&source) {
+            Ok(bytes) => bytes,
+            Err(err) => panic!(
+                "The embedded-data feature needs {} ({}). Set DESCRAMBLE_EMBED_DIR to a directory \
+                 holding data.json and templates.json.",
+                source.display(),
+                err
+            ),
+        };
+        let mut encoder: GzEncoder<File> = GzEncoder::new(
+            File::create(out_dir.join(format!("{}.gz", file))).unwrap(),
+            Compression::best(),
+        );
+        encoder.write_all(&bytes).unwrap();
+        encoder.finish().unwrap();
+    }
+}
@@ -1,59 +1,464 @@
-use crate::data::{Frequency, State};
+use crate::data::{
+    ALPHA_COUNT, DataError, Frequency, GroupId, POS_TAG_COUNT, POS_TAGS, State, Tag, WordId,
+    read_file, tag_index,
+};
+use crate::error::AppError;
+use crate::grammar::{Automaton, Pattern};
+use crate::scorer::PhraseContext;
 use indicatif::{ProgressBar, ProgressStyle};
 use itertools::Itertools;
-use std::collections::{HashMap, HashSet};
-use std::fs::read;
-use std::time::Duration;
-use std::usize;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::Deserialize;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 pub struct Solutions {
-    // A vector of solutions where each solution is a vector that has each frequency point to a vector of valid strings
-    // for that frequency.
-    solutions: Vec<Vec<Vec<String>>>,
+    // A vector of solutions where each solution is a vector of group ids, each pointing to a group
+    // of valid words spelled with the same letters.
+    solutions: Vec<Vec<GroupId>>,
+    stats: SearchStats,
+    coverage: Coverage,
+    // How much of the solutions were ranked, which shares the search's budget.
+    ranked: Coverage,
+    budget: Budget,
 }
-const POS_TAG_COUNT: usize = 14;
-const TEMPLATE_FIT_REWARD: f64 = 1.0;
-const TEMPLATE_UNFIT_PENALTY: f64 = 0.1;
-impl Solutions {
-    pub fn parse(&self, state: &State) -> Vec<((u32, u32), String, f64)> {
-        // Clunky but whatever.
-        let repr_index: HashMap<String, usize> = HashMap::from([
-            ("ADJ".to_string(), 0),
-            ("ADP".to_string(), 1),
-            ("ADV".to_string(), 2),
-            ("AUX".to_string(), 3),
-            ("CCONJ".to_string(), 4),
-            ("DET".to_string(), 5),
-            ("INTJ".to_string(), 6),
-            ("NOUN".to_string(), 7),
-            ("NUM".to_string(), 8),
-            ("PART".to_string(), 9),
-            ("PRON".to_string(), 10),
-            ("PROPN".to_string(), 11),
-            ("SCONJ".to_string(), 12),
-            ("VERB".to_string(), 13),
-        ]);
-        // POS frequency count to ordered template.
-        let templates: HashMap<[u8; POS_TAG_COUNT], Vec<String>> = {
-            let mut templates: HashMap<[u8; POS_TAG_COUNT], Vec<String>> = HashMap::default();
-            let raw_data: Vec<Vec<String>> = serde_json::from_slice(
-                &read(state.root_path.join("data").join("templates.json")).unwrap(),
-            )
-            .unwrap();
-            for template in raw_data {
-                let mut key: [u8; POS_TAG_COUNT] = [0; POS_TAG_COUNT];
-                for tag in template.iter() {
-                    key[repr_index[tag]] += 1;
-                }
-                templates.insert(key, template);
+/// How much of the search space was covered before the search stopped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Coverage {
+    Complete,
+    // Top-level branches searched to completion, out of all of them.
+    Branches(usize, usize),
+    // Results proven to be among the best, out of how many were asked for.
+    Proven(usize, usize),
+    // Solutions ranked, out of all that were found.
+    Ranked(usize, usize),
+}
+/// Stops the search early once the time limit passes or the user interrupts it.
+#[derive(Debug)]
+pub struct Budget {
+    deadline: Option<Instant>,
+    interrupted: Arc<AtomicBool>,
+}
+impl Budget {
+    pub fn new(time_limit: Option<f64>, interrupted: Arc<AtomicBool>) -> Self {
+        Budget {
+            deadline: time_limit.map(|secs| Instant::now() + Duration::from_secs_f64(secs)),
+            interrupted,
+        }
+    }
+    /// Once this is true it stays true.
+    pub fn expired(&self) -> bool {
+        self.interrupted.load(Ordering::Relaxed)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+/// Counters collected while searching.
+#[derive(Debug, Default)]
+pub struct SearchStats {
+    nodes: AtomicUsize,
+    cache_hits: AtomicUsize,
+    cache_misses: AtomicUsize,
+    cached_entries: usize,
+}
+impl SearchStats {
+    pub fn nodes(&self) -> usize {
+        self.nodes.load(Ordering::Relaxed)
+    }
+    pub fn cache_hits(&self) -> usize {
+        self.cache_hits.load(Ordering::Relaxed)
+    }
+    pub fn cache_misses(&self) -> usize {
+        self.cache_misses.load(Ordering::Relaxed)
+    }
+}
+const MEMO_SHARDS: usize = 64;
+/// Most orderings of one phrase that get scored. Repeating patterns fit long phrases in more
+/// orders than could ever be shown.
+pub const MAX_ORDERINGS: usize = 120;
+/// What is known about a set of remaining letters once it's been searched.
+#[derive(Debug, Clone)]
+pub enum Decomposition {
+    // No combination of the words spells it.
+    Dead,
+    // The words that can come next, each with the letters it leaves, which are either nothing or
+    // another set that can be spelled.
+    Edges(Arc<Vec<(usize, Frequency)>>),
+}
+#[derive(Default)]
+struct MemoShard {
+    edges: HashMap<Frequency, Arc<Vec<(usize, Frequency)>>>,
+    dead: HashSet<Frequency>,
+}
+/// Decompositions of remaining letters as a DAG, keyed by the remaining `Frequency`, solutions
+/// being the paths through it. Sharded so that threads rarely contend on the same lock.
+pub struct Memo {
+    shards: Vec<Mutex<MemoShard>>,
+}
+impl Default for Memo {
+    fn default() -> Self {
+        Memo {
+            shards: (0..MEMO_SHARDS)
+                .map(|_| Mutex::new(MemoShard::default()))
+                .collect(),
+        }
+    }
+}
+impl Memo {
+    fn shard(&self, remaining: &Frequency) -> &Mutex<MemoShard> {
+        let mut hasher: DefaultHasher = DefaultHasher::new();
+        remaining.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % MEMO_SHARDS]
+    }
+    pub fn get(&self, remaining: &Frequency) -> Option<Decomposition> {
+        let shard = self.shard(remaining).lock().unwrap();
+        if shard.dead.contains(remaining) {
+            return Some(Decomposition::Dead);
+        }
+        shard
+            .edges
+            .get(remaining)
+            .cloned()
+            .map(Decomposition::Edges)
+    }
+    pub fn insert(&self, remaining: Frequency, decomposition: Decomposition) {
+        let mut shard = self.shard(&remaining).lock().unwrap();
+        match decomposition {
+            Decomposition::Dead => {
+                shard.dead.insert(remaining);
+            }
+            Decomposition::Edges(edges) => {
+                shard.edges.insert(remaining, edges);
             }
-            templates
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| {
+                let shard = shard.lock().unwrap();
+                shard.edges.len() + shard.dead.len()
+            })
+            .sum()
+    }
+}
+/// A tag sequence phrases can be reordered to fit, and how much a fit is worth, from 0 to 1.
+pub struct Template {
+    pub tags: Vec<Tag>,
+    pub weight: f64,
+}
+/// One entry of `templates.json`: a list of tags, a pattern, or either with a weight.
+#[derive(Deserialize)]
+#[serde(
+    untagged,
+    deny_unknown_fields,
+    expecting = "expected a list of tags, a pattern, or an object with either and a \"weight\""
+)]
+enum TemplateEntry {
+    Tags(Vec<String>),
+    Pattern(String),
+    WeightedTags { tags: Vec<String>, weight: f64 },
+    WeightedPattern { pattern: String, weight: f64 },
+}
+/// A `templates.json`, either just its templates or its templates with the rules they use.
+#[derive(Deserialize)]
+#[serde(
+    untagged,
+    deny_unknown_fields,
+    expecting = "expected a list of templates, or an object with \"rules\" and \"templates\""
+)]
+enum TemplatesFile {
+    Templates(Vec<TemplateEntry>),
+    Grammar {
+        #[serde(default)]
+        rules: Vec<String>,
+        templates: Vec<TemplateEntry>,
+    },
+}
+/// POS tag templates compiled into one automaton. Phrases are reordered to every tag order it
+/// accepts, which are worked out once per multiset of tags.
+pub struct Templates {
+    automaton: Automaton,
+    // Each template as written, by its index in the automaton.
+    sources: Vec<String>,
+    orderings: Mutex<HashMap<[u8; POS_TAG_COUNT], Arc<Vec<Template>>>>,
+}
+impl Templates {
+    pub fn load(source: &Path) -> Result<Self, AppError> {
+        Templates::from_json(&read_file(source)?, source)
+    }
+    /// Reads the contents of a `templates.json`, `source` only names it in errors. A phrase
+    /// matching several templates takes the highest weight.
+    pub fn from_json(bytes: &[u8], source: &Path) -> Result<Self, AppError> {
+        let (rules, entries): (Vec<String>, Vec<TemplateEntry>) =
+            match serde_json::from_slice(bytes)
+                .map_err(|err| DataError::Json(source.to_path_buf(), err))?
+            {
+                TemplatesFile::Templates(templates) => (vec![], templates),
+                TemplatesFile::Grammar { rules, templates } => (rules, templates),
+            };
+        let pattern_error = |pattern: &str, reason: String| DataError::Pattern {
+            path: source.to_path_buf(),
+            pattern: pattern.to_string(),
+            reason,
         };
-
-        let mut parsed_solution: Vec<((u32, u32), String, f64)> = vec![];
-        // [[[statue, astute], [of], [liberty]], ...]
-        let solutions: &Vec<Vec<Vec<String>>> = &self.solutions;
+        let mut named: HashMap<String, Pattern> = HashMap::default();
+        for rule in rules.iter() {
+            let (name, pattern): (String, Pattern) =
+                Pattern::parse_rule(rule, &named).map_err(|reason| pattern_error(rule, reason))?;
+            named.insert(name, pattern);
+        }
+        let parse =
+            |text: &str| Pattern::parse(text, &named).map_err(|reason| pattern_error(text, reason));
+        let mut patterns: Vec<(Pattern, f64)> = vec![];
+        let mut sources: Vec<String> = vec![];
+        for entry in entries {
+            let (pattern, weight, entry): (Pattern, f64, String) = match entry {
+                TemplateEntry::Tags(tags) => {
+                    (Templates::sequence(&tags, source)?, 1.0, tags.join(" "))
+                }
+                TemplateEntry::WeightedTags { tags, weight } => {
+                    (Templates::sequence(&tags, source)?, weight, tags.join(" "))
+                }
+                TemplateEntry::Pattern(text) => (parse(&text)?, 1.0, text),
+                TemplateEntry::WeightedPattern { pattern, weight } => {
+                    (parse(&pattern)?, weight, pattern)
+                }
+            };
+            if !(0.0..=1.0).contains(&weight) {
+                return Err(AppError::InvalidWeight {
+                    path: source.to_path_buf(),
+                    entry,
+                    weight,
+                });
+            }
+            patterns.push((pattern, weight));
+            sources.push(entry);
+        }
+        Ok(Templates {
+            automaton: Automaton::new(&patterns),
+            sources,
+            orderings: Mutex::new(HashMap::default()),
+        })
+    }
+    /// A plain list of tags as a pattern.
+    fn sequence(tags: &[String], source: &Path) -> Result<Pattern, DataError> {
+        let sequence: Vec<Pattern> = tags
+            .iter()
+            .map(|tag| match tag_index(tag) {
+                Some(tag) => Ok(Pattern::Tag(tag)),
+                None => Err(DataError::UnknownTag {
+                    path: source.to_path_buf(),
+                    entry: tags.join(" "),
+                    tag: tag.clone(),
+                }),
+            })
+            .collect::<Result<Vec<Pattern>, DataError>>()?;
+        Ok(Pattern::Sequence(sequence))
+    }
+    /// Every order of `tags` that fits a template, with its weight, best first. At most
+    /// `MAX_ORDERINGS` of them.
+    pub fn orderings(&self, tags: &[Tag]) -> Arc<Vec<Template>> {
+        let key: [u8; POS_TAG_COUNT] = Templates::key(tags);
+        if let Some(orderings) = self.orderings.lock().unwrap().get(&key) {
+            return Arc::clone(orderings);
+        }
+        let orderings: Vec<Template> = self
+            .automaton
+            .orderings(&key, MAX_ORDERINGS)
+            .into_iter()
+            .map(|(tags, weight)| Template { tags, weight })
+            .collect();
+        let orderings: Arc<Vec<Template>> = Arc::new(orderings);
+        self.orderings
+            .lock()
+            .unwrap()
+            .insert(key, Arc::clone(&orderings));
+        orderings
+    }
+    /// The best template `tags` fits in this order, as written in `templates.json`, and its
+    /// weight.
+    pub fn matching(&self, tags: &[Tag]) -> Option<(&str, f64)> {
+        self.automaton
+            .matching(tags)
+            .map(|(index, weight)| (self.sources[index].as_str(), weight))
+    }
+    /// How many of each tag `tags` contains.
+    pub fn key(tags: &[Tag]) -> [u8; POS_TAG_COUNT] {
+        let mut key: [u8; POS_TAG_COUNT] = [0; POS_TAG_COUNT];
+        for tag in tags.iter() {
+            key[*tag as usize] += 1;
+        }
+        key
+    }
+}
+/// A partial solution waiting in the best-first queue, ordered by the bound on its score.
+struct Candidate {
+    bound: f64,
+    stack: Vec<usize>,
+    remaining: Frequency,
+    // Sum of the best word frequency of every group in `stack`.
+    sum: f64,
+}
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.bound.total_cmp(&other.bound).is_eq()
+    }
+}
+impl Eq for Candidate {}
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.bound.total_cmp(&other.bound)
+    }
+}
+/// The solution and ordering a phrase came from, its words and its raw score.
+pub type RankedEntry = ((u32, u32), Vec<WordId>, f64);
+/// A scored phrase competing for a place in the results. Among equal scores the one seen
+/// first ranks higher.
+struct RankedPhrase {
+    score: f64,
+    seen: usize,
+    entry: RankedEntry,
+}
+impl PartialEq for RankedPhrase {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+impl Eq for RankedPhrase {}
+impl PartialOrd for RankedPhrase {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for RankedPhrase {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then(other.seen.cmp(&self.seen))
+    }
+}
+/// A phrase waiting to be produced by `Phrases`. Its successors only ever advance groups from
+/// `pivot` onwards, so every combination is reached exactly once.
+struct PendingPhrase {
+    sum: f64,
+    odometer: Vec<usize>,
+    pivot: usize,
+}
+impl PartialEq for PendingPhrase {
+    fn eq(&self, other: &Self) -> bool {
+        self.sum.total_cmp(&other.sum).is_eq()
+    }
+}
+impl Eq for PendingPhrase {}
+impl PartialOrd for PendingPhrase {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for PendingPhrase {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.sum.total_cmp(&other.sum)
+    }
+}
+/// Every combination of one word per group of a solution, lazily and in descending order of
+/// mean word frequency.
+pub struct Phrases {
+    // Words of each group alongside their frequency, most frequent first.
+    groups: Vec<Vec<(WordId, f64)>>,
+    queue: BinaryHeap<PendingPhrase>,
+}
+impl Phrases {
+    pub fn new(solution: &[GroupId], state: &State) -> Self {
+        let groups: Vec<Vec<(WordId, f64)>> = solution
+            .iter()
+            .map(|group| {
+                let mut group: Vec<(WordId, f64)> = state
+                    .data
+                    .group(*group)
+                    .map(|id| (id, state.data.frequency(id)))
+                    .collect();
+                group.sort_by(|a, b| b.1.total_cmp(&a.1));
+                group
+            })
+            .collect();
+        let mut queue: BinaryHeap<PendingPhrase> = BinaryHeap::new();
+        if !groups.is_empty() && groups.iter().all(|group| !group.is_empty()) {
+            queue.push(PendingPhrase {
+                sum: groups.iter().map(|group| group[0].1).sum(),
+                odometer: vec![0; groups.len()],
+                pivot: 0,
+            });
+        }
+        Phrases { groups, queue }
+    }
+    /// Score of the next phrase without producing it.
+    pub fn peek_score(&self) -> Option<f64> {
+        self.queue
+            .peek()
+            .map(|pending| pending.sum / self.groups.len() as f64)
+    }
+}
+impl Iterator for Phrases {
+    type Item = (Vec<WordId>, f64);
+    fn next(&mut self) -> Option<Self::Item> {
+        let pending: PendingPhrase = self.queue.pop()?;
+        for i in pending.pivot..self.groups.len() {
+            let idx: usize = pending.odometer[i];
+            if idx + 1 < self.groups[i].len() {
+                let mut odometer: Vec<usize> = pending.odometer.clone();
+                odometer[i] += 1;
+                self.queue.push(PendingPhrase {
+                    sum: pending.sum - self.groups[i][idx].1 + self.groups[i][idx + 1].1,
+                    odometer,
+                    pivot: i,
+                });
+            }
+        }
+        let phrase: Vec<WordId> = pending
+            .odometer
+            .iter()
+            .enumerate()
+            .map(|(i, idx)| self.groups[i][*idx].0)
+            .collect();
+        Some((phrase, pending.sum / self.groups.len() as f64))
+    }
+}
+impl Solutions {
+    pub fn parse(&mut self, state: &State) -> Vec<((u32, u32), String, f64)> {
+        let (ranked, total_sum): (Vec<RankedEntry>, f64) = self.rank(state);
+        ranked
+            .into_iter()
+            .map(|(key, phrase, score)| {
+                let phrase: String = phrase.iter().map(|id| state.data.word(*id)).join(" ");
+                (key, phrase, (score / total_sum) * 100.0)
+            })
+            .collect()
+    }
+    /// The best `top_results` phrases with their raw scores, best first, and the sum of the scores
+    /// of every phrase. Once the budget runs out, stops as soon as it has that many phrases.
+    pub fn rank(&mut self, state: &State) -> (Vec<RankedEntry>, f64) {
+        let templates: &Templates = &state.templates;
+        let top_results: usize = state.args.top_results as usize;
+        // Only the best `top_results` phrases are kept, everything else only adds to the total.
+        let mut best: BinaryHeap<Reverse<RankedPhrase>> = BinaryHeap::new();
+        let mut total_sum: f64 = 0.0;
+        let mut seen: usize = 0;
+        // [[aelstttu, fo, beilrty], ...]
+        let solutions: &Vec<Vec<GroupId>> = &self.solutions;
         let pb: ProgressBar = ProgressBar::new(solutions.len() as u64);
         pb.set_style(
             ProgressStyle::with_template(
@@ -64,207 +469,522 @@ impl Solutions {
         );
         pb.set_message("Processing solutions...");
         for (i, solution) in solutions.iter().enumerate() {
-            // Holds possible final solutions, combinations of the final words. Needs reordering.
-            // [[astute, of, liberty], [statue, of, liberty]]
-            let mut phrases: Vec<(Vec<String>, f64)> = Solutions::get_phrases(&solution, state);
-            for (j, (phrase, score)) in phrases.iter_mut().enumerate() {
-                // phrase: [statue, of, liberty]
-                // Get the tags for the phrase.
-                let tags: Vec<String> = {
-                    let mut tags: Vec<String> = vec![];
-                    for word in phrase.iter() {
-                        let tag: &String = &state.data.string_data[word].tag;
-                        // Proper nouns can be treated as nouns.
-                        if tag != "PROPN" {
-                            tags.push(tag.clone());
-                        } else {
-                            tags.push("NOUN".to_string());
-                        }
+            if self.budget.expired() && best.len() >= top_results {
+                self.ranked = Coverage::Ranked(i, solutions.len());
+                break;
+            }
+            Solutions::score_solution(
+                i,
+                solution,
+                state,
+                templates,
+                f64::NEG_INFINITY,
+                &self.budget,
+                &mut |key: (u32, u32), phrase: Vec<WordId>, score: f64| {
+                    total_sum += score;
+                    best.push(Reverse(RankedPhrase {
+                        score,
+                        seen,
+                        entry: (key, phrase, score),
+                    }));
+                    seen += 1;
+                    if best.len() > top_results {
+                        best.pop();
                     }
-                    tags
-                };
-                // Get the key.
-                let key: [u8; POS_TAG_COUNT] = {
-                    let mut key: [u8; POS_TAG_COUNT] = [0; POS_TAG_COUNT];
-                    for tag in tags.iter() {
-                        key[repr_index[tag]] += 1;
+                },
+            );
+            pb.inc(1);
+        }
+        let ranked: Vec<RankedEntry> = best
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(ranked)| ranked.entry)
+            .collect();
+        (ranked, total_sum)
+    }
+    /// Every phrase of the `i`th solution, reordered to fit a template where possible and scored,
+    /// best first. Stops early once no phrase left can score above `floor`, or after the first
+    /// phrase once the budget runs out.
+    pub fn score_solution(
+        i: usize,
+        solution: &[GroupId],
+        state: &State,
+        templates: &Templates,
+        floor: f64,
+        budget: &Budget,
+        visit: &mut impl FnMut((u32, u32), Vec<WordId>, f64),
+    ) {
+        let context = |template_weight: Option<f64>| PhraseContext {
+            data: &state.data,
+            model: state.model.as_ref(),
+            template_weight,
+        };
+        let noun: Tag = tag_index("NOUN").unwrap();
+        let proper_noun: Tag = tag_index("PROPN").unwrap();
+        // Holds possible final solutions, combinations of the final words. Needs reordering.
+        // [[astute, of, liberty], [statue, of, liberty]]
+        let mut phrases: Phrases = Phrases::new(solution, state);
+        let mut j: usize = 0;
+        while phrases
+            .peek_score()
+            .is_some_and(|mean| state.scorer.bound(mean).is_none_or(|bound| bound > floor))
+        {
+            if j > 0 && budget.expired() {
+                break;
+            }
+            let (phrase, _): (Vec<WordId>, f64) = phrases.next().unwrap();
+            // phrase: [statue, of, liberty]
+            // Get the tags for the phrase.
+            let tags: Vec<Tag> = {
+                let mut tags: Vec<Tag> = vec![];
+                for word in phrase.iter() {
+                    let tag: Tag = state.data.tag(*word);
+                    // Proper nouns can be treated as nouns.
+                    if tag != proper_noun {
+                        tags.push(tag);
+                    } else {
+                        tags.push(noun);
+                    }
+                }
+                tags
+            };
+            // Map tags to the positions they were found in.
+            let pos_idx: HashMap<Tag, Vec<usize>> = {
+                let mut pos_idx: HashMap<Tag, Vec<usize>> = HashMap::default();
+                for (i, tag) in tags.iter().enumerate() {
+                    pos_idx.entry(*tag).or_default().push(i);
+                }
+                pos_idx
+            };
+            let orderings: Arc<Vec<Template>> = templates.orderings(&tags);
+            // Can fit a template, possibly in several orders, the best templates first.
+            let mut left: usize = MAX_ORDERINGS;
+            for template in orderings.iter() {
+                let phrases_indices: Vec<Vec<usize>> =
+                    Solutions::reorder(&template.tags, &pos_idx, left);
+                left -= phrases_indices.len();
+                for indices in phrases_indices {
+                    let phrase_solution: Vec<WordId> =
+                        indices.iter().map(|idx| phrase[*idx]).collect();
+                    let score: f64 = state
+                        .scorer
+                        .phrase(&phrase_solution, &context(Some(template.weight)));
+                    visit((i as u32, j as u32), phrase_solution, score);
+                }
+                if left == 0 {
+                    break;
+                }
+            }
+            if orderings.is_empty() {
+                let score: f64 = state.scorer.phrase(&phrase, &context(None));
+                visit((i as u32, j as u32), phrase, score);
+            }
+            j += 1;
+        }
+    }
+    /// Every way of placing the words at the positions in `pos_idx` into the slots of
+    /// `template`, up to `limit` of them. Words sharing a tag are permuted among that tag's slots,
+    /// lazily, so that long phrases of one tag don't build every permutation first.
+    pub fn reorder(
+        template: &[Tag],
+        pos_idx: &HashMap<Tag, Vec<usize>>,
+        limit: usize,
+    ) -> Vec<Vec<usize>> {
+        // Consistent key order, the first tag's permutations changing fastest.
+        let element_order: Vec<Tag> = pos_idx.keys().copied().sorted().rev().collect();
+        element_order
+            .iter()
+            .map(|tag| {
+                pos_idx[tag]
+                    .iter()
+                    .copied()
+                    .permutations(pos_idx[tag].len())
+            })
+            .multi_cartesian_product()
+            .take(limit)
+            .map(|order| {
+                // Each order follows the element order, one permutation per tag.
+                let mut weaved_element: Vec<usize> = vec![0; template.len()];
+                for (tag, tag_permutation) in element_order.iter().zip(order.iter()) {
+                    let slots = (0..template.len()).filter(|j| template[*j] == *tag);
+                    for (j, pos) in slots.zip(tag_permutation.iter()) {
+                        weaved_element[j] = *pos;
                     }
-                    key
+                }
+                weaved_element
+            })
+            .collect()
+    }
+    /// Expands partial solutions in order of an upper bound on the score of any phrase they can
+    /// still become, stopping once the best `top_results` phrases found so far beat every bound
+    /// left in the queue. The bound is the larger of the mean so far and the best word still
+    /// available, since adding words can never pull the mean above either, passed through the
+    /// scorer's own bound. Scorers without one, like a language model's, leave only the budget to
+    /// stop early on.
+    pub fn best_first(
+        state: &State,
+        groups: &[GroupId],
+        frequencies: &[Frequency],
+        anagram_frequency: &Frequency,
+        stats: &SearchStats,
+        budget: &Budget,
+    ) -> (HashSet<Vec<usize>>, Coverage) {
+        let templates: &Templates = &state.templates;
+        let word_count: usize = state.args.word_count as usize;
+        let top_results: usize = state.args.top_results as usize;
+        // Best word frequency of each group.
+        let group_max: Vec<f64> = groups
+            .iter()
+            .map(|group| {
+                state
+                    .data
+                    .group(*group)
+                    .map(|id| state.data.frequency(id))
+                    .fold(0.0, f64::max)
+            })
+            .collect();
+        let mut solutions: HashSet<Vec<usize>> = HashSet::default();
+        // Scores are non-negative, so their bit patterns order the same way they do.
+        let mut best: BinaryHeap<Reverse<u64>> = BinaryHeap::new();
+        let mut queue: BinaryHeap<Candidate> = BinaryHeap::from([Candidate {
+            bound: f64::INFINITY,
+            stack: vec![],
+            remaining: *anagram_frequency,
+            sum: 0.0,
+        }]);
+        while let Some(candidate) = queue.pop() {
+            stats.nodes.fetch_add(1, Ordering::Relaxed);
+            if budget.expired() {
+                // Nothing left in the queue can beat these.
+                let proven: usize = best
+                    .iter()
+                    .filter(|Reverse(score)| f64::from_bits(*score) >= candidate.bound)
+                    .count();
+                return (solutions, Coverage::Proven(proven, top_results));
+            }
+            if best.len() >= top_results
+                && best
+                    .peek()
+                    .is_none_or(|Reverse(kth)| f64::from_bits(*kth) >= candidate.bound)
+            {
+                break;
+            }
+            if candidate.remaining == Frequency::default() {
+                let solution: Vec<GroupId> =
+                    candidate.stack.iter().map(|idx| groups[*idx]).collect();
+                // Phrases that can't make it past the current worst of the best don't need scoring.
+                let floor: f64 = match best.peek() {
+                    Some(Reverse(kth)) if best.len() >= top_results => f64::from_bits(*kth),
+                    _ => f64::NEG_INFINITY,
                 };
-                // Can fit a template.
-                if templates.contains_key(&key) {
-                    let template: &Vec<String> = &templates[&key];
-                    // Map tags to the positions they were found in.
-                    let pos_idx: HashMap<String, Vec<usize>> = {
-                        let mut pos_idx: HashMap<String, Vec<usize>> = HashMap::default();
-                        for (i, tag) in tags.iter().enumerate() {
-                            if pos_idx.contains_key(tag) {
-                                pos_idx.get_mut(tag).unwrap().push(i);
-                            } else {
-                                pos_idx.insert(tag.to_string(), vec![i]);
-                            }
-                        }
-                        pos_idx
-                    };
-                    *score *= TEMPLATE_FIT_REWARD;
-                    let phrases_indices: Vec<Vec<usize>> = Solutions::reorder(&template, &pos_idx);
-                    let mut final_solutions: Vec<((u32, u32), String, f64)> = vec![];
-                    for indices in phrases_indices {
-                        let mut phrase_solution: Vec<String> = vec![];
-                        for idx in indices.iter() {
-                            phrase_solution.push(phrase[*idx].clone());
+                Solutions::score_solution(
+                    solutions.len(),
+                    &solution,
+                    state,
+                    templates,
+                    floor,
+                    budget,
+                    &mut |_, _, score: f64| {
+                        best.push(Reverse(score.to_bits()));
+                        if best.len() > top_results {
+                            best.pop();
                         }
-                        final_solutions.push((
-                            (i as u32, j as u32),
-                            phrase_solution.join(" "),
-                            *score,
-                        ));
-                    }
-                    parsed_solution.extend(final_solutions);
+                    },
+                );
+                solutions.insert(candidate.stack);
+                continue;
+            }
+            // Forces combinations instead of permutations.
+            let start: usize = candidate.stack.last().copied().unwrap_or(0);
+            let children: Vec<(usize, Frequency)> = (start..frequencies.len())
+                .filter_map(|idx| {
+                    let rest: Frequency = &candidate.remaining - &frequencies[idx];
+                    let depth: usize = candidate.stack.len() + 1;
+                    // With a word count, only the last word may use up the letters.
+                    let fits: bool = Frequency::is_valid(&rest)
+                        && (word_count == 0
+                            || (rest == Frequency::default()) == (depth == word_count));
+                    fits.then_some((idx, rest))
+                })
+                .collect();
+            let available: f64 = children
+                .iter()
+                .map(|(idx, _)| group_max[*idx])
+                .fold(0.0, f64::max);
+            for (idx, rest) in children {
+                let mut stack: Vec<usize> = candidate.stack.clone();
+                stack.push(idx);
+                let sum: f64 = candidate.sum + group_max[idx];
+                let mean: f64 = sum / stack.len() as f64;
+                let mean: f64 = if rest == Frequency::default() {
+                    mean
                 } else {
-                    *score *= TEMPLATE_UNFIT_PENALTY;
-                    parsed_solution.push(((i as u32, j as u32), phrase.join(" "), *score));
+                    mean.max(available)
+                };
+                queue.push(Candidate {
+                    bound: state.scorer.bound(mean).unwrap_or(f64::INFINITY),
+                    stack,
+                    remaining: rest,
+                    sum,
+                });
+            }
+        }
+        (solutions, Coverage::Complete)
+    }
+    /// The words that can come first in a combination of `frequencies` summing to exactly
+    /// `remaining`, with what each leaves. Only branches over the candidates of the rarest
+    /// remaining letter, since one of them must be used. Results are memoized, including the dead
+    /// ends, and `expand` turns them into the combinations themselves.
+    pub fn decompose(
+        remaining: &Frequency,
+        frequencies: &[Frequency],
+        by_letter: &[Vec<usize>],
+        memo: &Memo,
+        stats: &SearchStats,
+        budget: &Budget,
+    ) -> Decomposition {
+        if budget.expired() {
+            return Decomposition::Dead;
+        }
+        stats.nodes.fetch_add(1, Ordering::Relaxed);
+        if let Some(decomposition) = memo.get(remaining) {
+            stats.cache_hits.fetch_add(1, Ordering::Relaxed);
+            return decomposition;
+        }
+        stats.cache_misses.fetch_add(1, Ordering::Relaxed);
+        let mut edges: Vec<(usize, Frequency)> = vec![];
+        if let Some(letter) = Solutions::rarest_letter(remaining, by_letter) {
+            for idx in by_letter[letter].iter() {
+                if budget.expired() {
+                    break;
+                }
+                let rest: Frequency = remaining - &frequencies[*idx];
+                if rest == Frequency::default()
+                    || (Frequency::is_valid(&rest)
+                        && matches!(
+                            Solutions::decompose(
+                                &rest,
+                                frequencies,
+                                by_letter,
+                                memo,
+                                stats,
+                                budget
+                            ),
+                            Decomposition::Edges(_)
+                        ))
+                {
+                    edges.push((*idx, rest));
                 }
             }
-            pb.inc(1);
         }
-        // Final normalization.
-        let mut total_sum: f64 = 0.0;
-        for solution in parsed_solution.iter() {
-            total_sum += solution.2;
+        let decomposition: Decomposition = if edges.is_empty() {
+            Decomposition::Dead
+        } else {
+            Decomposition::Edges(Arc::new(edges))
+        };
+        // Something below may have been cut short, so this isn't the full answer.
+        if !budget.expired() {
+            memo.insert(*remaining, decomposition.clone());
         }
-        for solution in parsed_solution.iter_mut() {
-            solution.2 = (solution.2 / total_sum) * 100.0;
+        decomposition
+    }
+    /// Adds every path through the memo from `edges` to `found`, each on top of `stack` and
+    /// sorted, which forces combinations instead of permutations. Sets that weren't memoized,
+    /// because the budget ran out before they were finished, lead nowhere. Once the budget runs
+    /// out, stops as soon as `found` holds `keep` solutions.
+    pub fn expand(
+        edges: &[(usize, Frequency)],
+        memo: &Memo,
+        stack: &mut Vec<usize>,
+        found: &mut HashSet<Vec<usize>>,
+        keep: usize,
+        budget: &Budget,
+    ) {
+        for (idx, rest) in edges {
+            if found.len() >= keep && budget.expired() {
+                return;
+            }
+            stack.push(*idx);
+            if *rest == Frequency::default() {
+                found.insert(stack.iter().copied().sorted().collect());
+            } else if let Some(Decomposition::Edges(next)) = memo.get(rest) {
+                Solutions::expand(&next, memo, stack, found, keep, budget);
+            }
+            stack.pop();
         }
-        parsed_solution.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap());
-        if parsed_solution.len() < state.args.top_results as usize {
-            return parsed_solution;
+    }
+    /// The letter left in `remaining` with the fewest candidates, ties going to the earlier letter.
+    /// `None` once nothing is left, or if a remaining letter has no candidates at all.
+    pub fn rarest_letter(remaining: &Frequency, by_letter: &[Vec<usize>]) -> Option<usize> {
+        let letter: usize = (0..by_letter.len())
+            .filter(|letter| remaining.get(*letter) > 0)
+            .min_by_key(|letter| by_letter[*letter].len())?;
+        if by_letter[letter].is_empty() {
+            return None;
         }
-        parsed_solution[0..state.args.top_results as usize].to_vec()
+        Some(letter)
     }
-    pub fn reorder(
-        template: &Vec<String>,
-        pos_idx: &HashMap<String, Vec<usize>>,
-    ) -> Vec<Vec<usize>> {
-        // Map the positions inside each POS tag in POS_IDX to the template.
-        // Maps POS tags to their individual order permutations.
-        // "NOUN: [(0, 1), (1, 0)], ADJ: [(2)]"
-        let mut subpermutations: HashMap<String, Vec<Vec<usize>>> = HashMap::default();
-        for pos_id in pos_idx.keys() {
-            let data: &Vec<usize> = &pos_idx[pos_id];
-            let permutations: Vec<Vec<usize>> =
-                data.iter().cloned().permutations(data.len()).collect();
-            subpermutations.insert(pos_id.clone(), permutations);
-        }
-        // Consistent key order.
-        let cartesian_element_order: Vec<String> = {
-            let mut cartesian_element_order: Vec<String> = vec![];
-            for key in subpermutations.keys() {
-                cartesian_element_order.push(key.clone());
+    /// Visits every non-decreasing extension of `stack` up to `length` indices whose sum still
+    /// fits within `bound`, or as many as it can before the budget runs out.
+    pub fn for_each_combination(
+        frequencies: &[Frequency],
+        bound: &Frequency,
+        length: usize,
+        stack: &mut Vec<usize>,
+        sum: &Frequency,
+        visit: &mut impl FnMut(&[usize], &Frequency),
+        budget: &Budget,
+    ) {
+        if budget.expired() {
+            return;
+        }
+        if stack.len() == length {
+            visit(stack, sum);
+            return;
+        }
+        let start: usize = stack.last().copied().unwrap_or(0);
+        for i in start..frequencies.len() {
+            let next: Frequency = sum + &frequencies[i];
+            if Frequency::is_valid(&(bound - &next)) {
+                stack.push(i);
+                Solutions::for_each_combination(
+                    frequencies,
+                    bound,
+                    length,
+                    stack,
+                    &next,
+                    visit,
+                    budget,
+                );
+                stack.pop();
             }
-            cartesian_element_order
-        };
-
-        // Get the cartesian product of these elements. -> (0 1 2, 1 0 2)
-        // Means first noun permut, second adj permut, third verb permut, etc...
-        // Map these permutations to the template itself in order.
-        let mut orders: Vec<Vec<usize>> = vec![];
-        let mut odometer: Vec<usize> = vec![0; subpermutations.len()];
-        let odo_len: usize = odometer.len();
-        'main: loop {
-            orders.push(odometer.clone());
-            for i in 0..odo_len {
-                if odometer[i] < subpermutations[&cartesian_element_order[i]].len() - 1 {
-                    odometer[i] += 1;
-                    break;
-                } else if i == odo_len - 1 {
-                    break 'main;
-                } else {
-                    for j in 0..=i {
-                        odometer[j] = 0;
-                    }
-                }
+        }
+    }
+    pub fn display_coverage(&self) {
+        for coverage in [self.coverage, self.ranked] {
+            match coverage {
+                Coverage::Complete => {}
+                Coverage::Branches(searched, total) => println!(
+                    "Search incomplete. Searched {} of {} top-level branches ({:.2}%).",
+                    searched,
+                    total,
+                    if total == 0 {
+                        0.0
+                    } else {
+                        searched as f64 / total as f64 * 100.0
+                    },
+                ),
+                Coverage::Proven(proven, wanted) => println!(
+                    "Search incomplete. {} of the top {} results are proven, the rest may be outranked.",
+                    proven, wanted,
+                ),
+                Coverage::Ranked(ranked, total) => println!(
+                    "Ranking incomplete. Ranked {} of {} solutions found.",
+                    ranked, total,
+                ),
             }
         }
-        let mut weaved_elements: Vec<Vec<usize>> = vec![];
-        // We go through each conceived order
-        for order in orders {
-            // Each order follows the element order, so we loop through that.
-            let mut weaved_element: Vec<usize> = vec![0; template.len()];
-            for (i, tag) in cartesian_element_order.iter().enumerate() {
-                // For each tag, we get the corresponding permutation described by the order.
-                let tag_permutation: &Vec<usize> = &subpermutations[tag][order[i]];
-                let mut matched: usize = 0;
-                for (j, pos) in template.iter().enumerate() {
-                    if pos == tag {
-                        weaved_element[j] = tag_permutation[matched];
-                        matched += 1
-                    }
-                }
+    }
+    pub fn display_stats(&self) {
+        let lookups: usize = self.stats.cache_hits() + self.stats.cache_misses();
+        eprintln!(
+            "Nodes visited: {}\nCache hits: {}\nCache misses: {}\nCache hit rate: {:.2}%\nCached entries: {}",
+            self.stats.nodes(),
+            self.stats.cache_hits(),
+            self.stats.cache_misses(),
+            if lookups == 0 {
+                0.0
+            } else {
+                self.stats.cache_hits() as f64 / lookups as f64 * 100.0
+            },
+            self.stats.cached_entries,
+        );
+    }
+    /// Every ranked phrase with what went into its score: each word's frequency and tag, the
+    /// template it was fit to, the scorer's contributions and the raw score the percentage is
+    /// taken from.
+    pub fn display_explanation(state: &State, ranked: &[RankedEntry], total_sum: f64) {
+        let noun: Tag = tag_index("NOUN").unwrap();
+        let proper_noun: Tag = tag_index("PROPN").unwrap();
+        let mut display_string: String = String::new();
+        for (_, phrase, score) in ranked {
+            let words: Vec<&str> = phrase.iter().map(|id| state.data.word(*id)).collect();
+            let width: usize = words.iter().map(|word| word.len()).max().unwrap_or(0);
+            display_string += &format!("{} - {:.2}%\n", words.join(" "), score / total_sum * 100.0);
+            for (word, id) in words.iter().zip(phrase.iter()) {
+                display_string += &format!(
+                    "    {:width$}  {:5}  {:.3e}\n",
+                    word,
+                    POS_TAGS[state.data.tag(*id) as usize],
+                    state.data.frequency(*id),
+                );
             }
-            weaved_elements.push(weaved_element);
-        }
-        weaved_elements
-    }
-    pub fn get_phrases(words: &Vec<Vec<String>>, state: &State) -> Vec<(Vec<String>, f64)> {
-        let mut phrases: Vec<(Vec<String>, f64)> = vec![];
-        let mut odometer: Vec<usize> = vec![0; words.len()];
-        let odo_len: usize = odometer.len();
-        'main: loop {
-            let mut phrase_mean: f64 = 0.0;
-            let phrase_words: Vec<String> = {
-                let mut phrase_words: Vec<String> = vec![];
-                for (i, idx) in odometer.iter().enumerate() {
-                    phrase_mean += state.data.string_data[&words[i][*idx]].frequency;
-                    phrase_words.push(words[i][*idx].clone());
-                }
-                phrase_mean /= odo_len as f64;
-                phrase_words
+            // Proper nouns can be treated as nouns, the same as when scoring.
+            let tags: Vec<Tag> = phrase
+                .iter()
+                .map(|id| match state.data.tag(*id) {
+                    tag if tag == proper_noun => noun,
+                    tag => tag,
+                })
+                .collect();
+            let matching: Option<(&str, f64)> = state.templates.matching(&tags);
+            display_string += &match matching {
+                Some((template, weight)) => format!(
+                    "    template: {} (weight {}) as {}\n",
+                    template,
+                    weight,
+                    tags.iter().map(|tag| POS_TAGS[*tag as usize]).join(" ")
+                ),
+                None => String::from("    template: none\n"),
             };
-            phrases.push((phrase_words, phrase_mean));
-            for i in 0..odo_len {
-                if odometer[i] < words[i].len() - 1 {
-                    odometer[i] += 1;
-                    break;
-                } else if i == odo_len - 1 {
-                    break 'main;
-                } else {
-                    for j in 0..=i {
-                        odometer[j] = 0
-                    }
-                }
+            let context: PhraseContext = PhraseContext {
+                data: &state.data,
+                model: state.model.as_ref(),
+                template_weight: matching.map(|(_, weight)| weight),
+            };
+            for line in state.scorer.explain(phrase, &context) {
+                display_string += &format!("    {}\n", line);
             }
+            display_string += &format!("    score: {:.3e} of {:.3e}\n", score, total_sum);
         }
-        phrases
+        print!("{}", display_string);
     }
-    pub fn display_solution(solutions: Vec<((u32, u32), String, f64)>) {
-        let mut original_order: Vec<(u32, u32)> = vec![];
-        let mut format_variations: HashMap<(u32, u32), (Vec<String>, f64)> = HashMap::default();
-        for solution in solutions {
-            if format_variations.contains_key(&solution.0) {
-                format_variations
-                    .get_mut(&solution.0)
-                    .unwrap()
-                    .0
-                    .push(solution.1);
-            } else {
-                format_variations.insert(solution.0, (vec![solution.1], solution.2));
-                original_order.push(solution.0);
+    /// Orderings of the same phrase that score the same, up to rounding, share a line. Ones that
+    /// don't, because of a template's weight or a language model, get their own.
+    pub fn group_orderings(solutions: Vec<((u32, u32), String, f64)>) -> Vec<(Vec<String>, f64)> {
+        let mut lines: Vec<(Vec<String>, f64)> = vec![];
+        // Lines of each phrase.
+        let mut phrase_lines: HashMap<(u32, u32), Vec<usize>> = HashMap::default();
+        for (key, phrase, score) in solutions {
+            let same_phrase: &mut Vec<usize> = phrase_lines.entry(key).or_default();
+            let same_score: Option<&usize> = same_phrase
+                .iter()
+                .find(|line| (lines[**line].1 - score).abs() <= lines[**line].1.abs() * 1e-9);
+            match same_score {
+                Some(line) => lines[*line].0.push(phrase),
+                None => {
+                    same_phrase.push(lines.len());
+                    lines.push((vec![phrase], score));
+                }
             }
         }
+        lines
+    }
+    pub fn display_solution(solutions: Vec<((u32, u32), String, f64)>) {
+        let lines: Vec<(Vec<String>, f64)> = Solutions::group_orderings(solutions);
         let mut max_sequence_length: usize = 0;
         let mut display_string: Vec<u8> = vec![];
-        for key in &original_order {
-            let string_value: String = (format_variations[&key].0).join(" / ");
+        for line in &lines {
+            let string_value: String = line.0.join(" / ");
             let str_len: usize = string_value.len();
             if max_sequence_length < str_len {
                 max_sequence_length = str_len;
             }
         }
-        for key in &original_order {
-            let string_value: String = (format_variations[&key].0).join(" / ");
+        for line in &lines {
+            let string_value: String = line.0.join(" / ");
             let str_len: usize = string_value.len();
-            let float_value: f64 = format_variations[&key].1;
+            let float_value: f64 = line.1;
             if max_sequence_length < str_len {
                 max_sequence_length = str_len;
             }
@@ -286,32 +1006,68 @@ impl From<&State> for Solutions {
         let threshold: f64 =
             { 1e-9_f64 + (1e-4_f64 - 1e-9_f64) * (f64::from(state.args.strength) / 10_f64) };
         let anagram_frequency: Frequency = Frequency::from(state.args.anagram.as_bytes());
-        // `frequencies` is already pre-filtered from the initial list based on frequency.
-        let frequencies: Vec<Frequency> = {
-            let mut frequencies: Vec<Frequency> = vec![];
-            for freq in state.data.string_mapping.keys() {
-                // Prunes frequencies where every string goes below the threshold.
+        // `groups` is already pre-filtered from the initial list based on frequency.
+        let groups: Vec<GroupId> = {
+            let mut groups: Vec<GroupId> = vec![];
+            // Only groups whose letters fit within the anagram.
+            for group in state.data.index.sub_multisets_of(&anagram_frequency).iter() {
+                // Prunes groups where every string goes below the threshold.
                 let passes: bool = {
                     let mut flag: bool = false;
-                    for str in &state.data.string_mapping[freq] {
-                        if state.data.string_data[str].frequency > threshold {
+                    for id in state.data.group(*group) {
+                        if state.data.frequency(id) > threshold {
                             flag = true;
                             break;
                         }
                     }
                     flag
                 };
-                // If it fits within the anagram and at least one of the constituent strings pass frequency threshold.
-                if Frequency::is_valid(&(&anagram_frequency - freq)) && passes {
-                    frequencies.push(*freq);
+                // At least one of the constituent strings pass frequency threshold.
+                if passes {
+                    groups.push(*group);
+                }
+            }
+            // Fixed order so that results don't depend on hashing. Group ids follow key order.
+            groups.sort();
+            groups
+        };
+        let frequencies: Vec<Frequency> = groups
+            .iter()
+            .map(|group| state.data.group_key(*group))
+            .collect();
+        // Indices of the frequencies that contain each letter.
+        let by_letter: Vec<Vec<usize>> = {
+            let mut by_letter: Vec<Vec<usize>> = vec![vec![]; ALPHA_COUNT];
+            for (i, freq) in frequencies.iter().enumerate() {
+                for (letter, count) in freq.arr().iter().enumerate() {
+                    if *count > 0 {
+                        by_letter[letter].push(i);
+                    }
                 }
             }
-            frequencies
+            by_letter
         };
         let branches: usize = frequencies.len();
         let mut solutions: HashSet<Vec<usize>> = HashSet::default();
+        let mut stats: SearchStats = SearchStats::default();
+        let mut coverage: Coverage = Coverage::Complete;
+        let budget: Budget = Budget::new(state.args.time_limit, Arc::clone(&state.interrupted));
+        let pool: ThreadPool = ThreadPoolBuilder::new()
+            .num_threads(state.args.threads)
+            .build()
+            .unwrap();
         if branches > 0 {
             match state.args.word_count {
+                _ if state.args.best_first => {
+                    (solutions, coverage) = Solutions::best_first(
+                        state,
+                        &groups,
+                        &frequencies,
+                        &anagram_frequency,
+                        &stats,
+                        &budget,
+                    );
+                }
                 0 => {
                     let spinner: ProgressBar = ProgressBar::new_spinner();
                     spinner.set_style(
@@ -337,44 +1093,55 @@ impl From<&State> for Solutions {
                     );
                     spinner.set_message("Finding solutions...");
                     spinner.enable_steady_tick(Duration::from_millis(100));
-                    // Set stack and sum caching to avoid repeated recalculations.
-                    let mut sum_cache: Frequency = Frequency::from(0);
-                    let mut stack: Vec<usize> = vec![0];
-                    'main: loop {
-                        // Get depth and sum using current and cache.
-                        let mut depth: usize = stack.len() - 1;
-                        let sum: Frequency = &sum_cache + &frequencies[stack[depth]];
-                        let validity: bool = Frequency::is_valid(&(&anagram_frequency - &sum));
-                        // Valid but not the answer. Less than frequency
-                        if validity && sum != anagram_frequency {
-                            sum_cache = sum;
-                            // Forces combinations instead of permutations.
-                            stack.push(*stack.last().unwrap());
-                            continue;
-                        // Is the answer.
-                        } else if sum == anagram_frequency {
-                            solutions.insert(stack.clone());
-                        }
-                        loop {
-                            depth = stack.len() - 1;
-                            // Can still iterate through depth.
-                            if stack[depth] < branches - 1 {
-                                stack[depth] += 1;
-                                break;
-                            // Go up to sibling in next iteration.
-                            } else {
-                                // We're already at the root, break.
-                                if depth == 0 {
-                                    break 'main;
-                                // We can still go higher.
-                                } else {
-                                    // We pop the cache by subtracting the frequency that the pointer
-                                    // is pointing to above.
-                                    sum_cache = &sum_cache - &frequencies[stack[depth - 1]];
-                                    stack.pop();
+                    // Each candidate for the rarest letter is searched independently on the pool,
+                    // sharing decompositions of the remaining letters through the memo, then merged.
+                    let memo: Memo = Memo::default();
+                    let roots: &[usize] =
+                        match Solutions::rarest_letter(&anagram_frequency, &by_letter) {
+                            Some(letter) => &by_letter[letter],
+                            None => &[],
+                        };
+                    let searched: AtomicUsize = AtomicUsize::new(0);
+                    solutions = pool.install(|| {
+                        roots
+                            .par_iter()
+                            .map(|root| {
+                                let mut found: HashSet<Vec<usize>> = HashSet::default();
+                                let remaining: Frequency = &anagram_frequency - &frequencies[*root];
+                                if remaining == Frequency::default() {
+                                    found.insert(vec![*root]);
+                                } else if Frequency::is_valid(&remaining)
+                                    && let Decomposition::Edges(edges) = Solutions::decompose(
+                                        &remaining,
+                                        &frequencies,
+                                        &by_letter,
+                                        &memo,
+                                        &stats,
+                                        &budget,
+                                    )
+                                {
+                                    Solutions::expand(
+                                        &edges,
+                                        &memo,
+                                        &mut vec![*root],
+                                        &mut found,
+                                        state.args.top_results as usize,
+                                        &budget,
+                                    );
                                 }
-                            }
-                        }
+                                if !budget.expired() {
+                                    searched.fetch_add(1, Ordering::Relaxed);
+                                }
+                                found
+                            })
+                            .reduce(HashSet::default, |mut merged, branch| {
+                                merged.extend(branch);
+                                merged
+                            })
+                    });
+                    stats.cached_entries = memo.len();
+                    if budget.expired() {
+                        coverage = Coverage::Branches(searched.into_inner(), roots.len());
                     }
                 }
                 1..=u8::MAX => {
@@ -400,60 +1167,433 @@ impl From<&State> for Solutions {
                             .progress_chars("▪▪ "),
                         );
                         pb.set_message("Finding solutions...");
-                        let mut odometer: Vec<usize> =
-                            vec![0; (state.args.word_count - 1) as usize];
-                        'main: loop {
-                            pb.set_position(odometer[0] as u64);
-                            let sum = {
-                                let mut sum: Frequency = Frequency::default();
-                                for idx in odometer.iter() {
-                                    sum = &sum + &frequencies[*idx];
-                                }
-                                sum
-                            };
-                            let other = &anagram_frequency - &sum;
-                            if freq_idx.contains_key(&other) {
-                                let mut solution = odometer.clone();
-                                solution.push(freq_idx[&other]);
-                                solution.sort();
-                                solutions.insert(solution);
-                            }
-                            for i in (0..odometer.len()).rev() {
-                                // Has not rolled over.
-                                if odometer[i] < branches - 1 {
-                                    odometer[i] += 1;
-                                    break;
-                                // Should roll over. But breaks since we're at the root.
-                                } else if i == 0 {
-                                    break 'main;
-                                // Roll over.
-                                } else {
-                                    for j in i..odometer.len() {
-                                        if odometer[i - 1] + 1 < branches {
-                                            odometer[j] = odometer[i - 1] + 1;
-                                        }
+                        // Meet in the middle. Every sorted solution is split into a left half holding
+                        // its smallest indices and a right half holding the rest.
+                        let left_length: usize = (state.args.word_count / 2) as usize;
+                        let right_length: usize = state.args.word_count as usize - left_length;
+                        // Valid left halves keyed by their sum.
+                        let halves: HashMap<Frequency, Vec<Vec<usize>>> = {
+                            let mut halves: HashMap<Frequency, Vec<Vec<usize>>> =
+                                HashMap::default();
+                            Solutions::for_each_combination(
+                                &frequencies,
+                                &anagram_frequency,
+                                left_length,
+                                &mut vec![],
+                                &Frequency::default(),
+                                &mut |half: &[usize], sum: &Frequency| {
+                                    halves.entry(*sum).or_default().push(half.to_vec());
+                                },
+                                &budget,
+                            );
+                            halves
+                        };
+                        // Right halves are fanned out by their first index and joined against
+                        // the left halves that complete them.
+                        let searched: AtomicUsize = AtomicUsize::new(0);
+                        solutions = pool.install(|| {
+                            (0..branches)
+                                .into_par_iter()
+                                .map(|root| {
+                                    let mut found: HashSet<Vec<usize>> = HashSet::default();
+                                    Solutions::for_each_combination(
+                                        &frequencies,
+                                        &anagram_frequency,
+                                        right_length,
+                                        &mut vec![root],
+                                        &frequencies[root],
+                                        &mut |half: &[usize], sum: &Frequency| {
+                                            let other: Frequency = &anagram_frequency - sum;
+                                            if let Some(lefts) = halves.get(&other) {
+                                                for left in lefts {
+                                                    // Forces combinations instead of permutations.
+                                                    if left[left_length - 1] <= half[0] {
+                                                        let mut solution: Vec<usize> = left.clone();
+                                                        solution.extend_from_slice(half);
+                                                        found.insert(solution);
+                                                    }
+                                                }
+                                            }
+                                        },
+                                        &budget,
+                                    );
+                                    if !budget.expired() {
+                                        searched.fetch_add(1, Ordering::Relaxed);
                                     }
-                                }
-                            }
+                                    pb.inc(1);
+                                    found
+                                })
+                                .reduce(HashSet::default, |mut merged, branch| {
+                                    merged.extend(branch);
+                                    merged
+                                })
+                        });
+                        if budget.expired() {
+                            coverage = Coverage::Branches(searched.into_inner(), branches);
                         }
                     }
                 }
             }
         }
-        // 3D Vectors due to storing vector solutions where each frequency points to vectors of strings.
-        let raw_solution: Vec<Vec<Vec<String>>> = {
-            let mut raw: Vec<Vec<Vec<String>>> = vec![];
-            for solution in solutions {
-                let mut solution_buffer: Vec<Vec<String>> = vec![];
-                for idx in solution {
-                    solution_buffer.push(state.data.string_mapping[&frequencies[idx]].clone());
-                }
-                raw.push(solution_buffer);
+        // Solutions keep their group ids, the words themselves are looked up when parsing.
+        let raw_solution: Vec<Vec<GroupId>> = {
+            let mut raw: Vec<Vec<GroupId>> = vec![];
+            for solution in solutions.into_iter().sorted() {
+                raw.push(solution.iter().map(|idx| groups[*idx]).collect());
             }
             raw
         };
         Solutions {
             solutions: raw_solution,
+            stats,
+            coverage,
+            ranked: Coverage::Complete,
+            budget,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::algorithm::{
+        Budget, Coverage, Decomposition, MAX_ORDERINGS, Memo, RankedEntry, SearchStats, Solutions,
+        Templates,
+    };
+    use crate::data::{ALPHA_COUNT, Args, Command, Data, Frequency, State, Tag, WordId, tag_index};
+    use crate::error::AppError;
+    use crate::scorer::ScorerSpec;
+    use crate::words::WordFilter;
+    use clap::Parser;
+    use itertools::Itertools;
+    use std::collections::{HashMap, HashSet};
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    #[test]
+    fn test_group_orderings() {
+        let lines = Solutions::group_orderings(vec![
+            ((0, 0), String::from("statue of liberty"), 30.0),
+            ((0, 0), String::from("liberty of statue"), 30.0 + 1e-12),
+            ((1, 0), String::from("of astute liberty"), 30.0),
+            ((0, 0), String::from("statue liberty of"), 10.0),
+        ]);
+        let lines: Vec<(String, f64)> = lines
+            .into_iter()
+            .map(|(phrases, score)| (phrases.join(" / "), score))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (String::from("statue of liberty / liberty of statue"), 30.0),
+                (String::from("of astute liberty"), 30.0),
+                (String::from("statue liberty of"), 10.0),
+            ]
+        );
+    }
+    #[test]
+    fn test_long_repetition() {
+        let json = br#"["NOUN+", "(NOUN | VERB)+", "(NOUN | ADJ)* DET"]"#;
+        let templates = Templates::from_json(json, Path::new("templates.json")).unwrap();
+        let noun: Tag = tag_index("NOUN").unwrap();
+        let verb: Tag = tag_index("VERB").unwrap();
+        let nouns: Vec<Tag> = vec![noun; 8];
+        assert_eq!(templates.orderings(&nouns).len(), 1);
+        let pos_idx: HashMap<Tag, Vec<usize>> = HashMap::from([(noun, (0..8).collect())]);
+        let orders: Vec<Vec<usize>> = Solutions::reorder(&nouns, &pos_idx, MAX_ORDERINGS);
+        assert_eq!(orders.len(), MAX_ORDERINGS);
+        assert!(orders.iter().all_unique());
+        assert!(orders.iter().all(|order| {
+            order
+                .iter()
+                .sorted()
+                .eq((0..8).collect::<Vec<usize>>().iter())
+        }));
+        // 252 orders of five nouns and five verbs fit, only the first are kept.
+        let mixed: Vec<Tag> = [vec![noun; 5], vec![verb; 5]].concat();
+        assert_eq!(templates.orderings(&mixed).len(), MAX_ORDERINGS);
+        let adjectives: Vec<Tag> = vec![tag_index("ADJ").unwrap(); 12];
+        assert!(templates.orderings(&adjectives).is_empty());
+        // A heavier template is found even when the lighter one has more orders than are kept.
+        let json = br#"[
+            {"pattern": "(NOUN | VERB)+", "weight": 0.2},
+            {"tags": ["VERB", "VERB", "VERB", "VERB", "VERB", "NOUN", "NOUN", "NOUN", "NOUN", "NOUN"], "weight": 1.0}
+        ]"#;
+        let templates = Templates::from_json(json, Path::new("templates.json")).unwrap();
+        let orderings = templates.orderings(&mixed);
+        assert_eq!(orderings.len(), MAX_ORDERINGS);
+        assert_eq!(orderings[0].weight, 1.0);
+        assert_eq!(orderings[0].tags, [vec![verb; 5], vec![noun; 5]].concat());
+        assert!(orderings[1..].iter().all(|template| template.weight == 0.2));
+    }
+    #[test]
+    fn test_template_orderings() {
+        let json = br#"[
+            {"tags": ["NOUN", "VERB", "DET", "NOUN"], "weight": 0.3},
+            {"tags": ["DET", "NOUN", "VERB", "NOUN"], "weight": 0.5},
+            {"tags": ["NOUN", "VERB", "DET", "NOUN"], "weight": 0.8},
+            ["ADJ", "NOUN"]
+        ]"#;
+        let templates = Templates::from_json(json, Path::new("templates.json")).unwrap();
+        let tags: Vec<Tag> = ["VERB", "NOUN", "NOUN", "DET"]
+            .iter()
+            .map(|tag| tag_index(tag).unwrap())
+            .collect();
+        let mut orderings: Vec<f64> = templates
+            .orderings(&tags)
+            .iter()
+            .map(|template| template.weight)
+            .collect();
+        orderings.sort_by(f64::total_cmp);
+        assert_eq!(orderings, [0.5, 0.8]);
+        assert!(templates.orderings(&tags[..2]).is_empty());
+        let ordered: Vec<Tag> = ["DET", "NOUN", "VERB", "NOUN"]
+            .iter()
+            .map(|tag| tag_index(tag).unwrap())
+            .collect();
+        assert_eq!(
+            templates.matching(&ordered),
+            Some(("DET NOUN VERB NOUN", 0.5))
+        );
+        let invalid = br#"[{"tags": ["NOUN"], "weight": 1.5}]"#;
+        assert!(matches!(
+            Templates::from_json(invalid, Path::new("templates.json")),
+            Err(AppError::InvalidWeight { .. })
+        ));
+    }
+    const ANAGRAM: &str = "Built to stay free.";
+    const FIXTURE: &[u8] = br#"{
+        "statue": {"frequency": 0.04, "tag": "NOUN"},
+        "astute": {"frequency": 0.002, "tag": "ADJ"},
+        "of": {"frequency": 0.25, "tag": "ADP"},
+        "liberty": {"frequency": 0.01, "tag": "NOUN"},
+        "titles": {"frequency": 0.003, "tag": "NOUN"},
+        "for": {"frequency": 0.08, "tag": "ADP"},
+        "beauty": {"frequency": 0.005, "tag": "NOUN"},
+        "built": {"frequency": 0.02, "tag": "VERB"},
+        "to": {"frequency": 0.2, "tag": "ADP"},
+        "stay": {"frequency": 0.01, "tag": "VERB"},
+        "free": {"frequency": 0.03, "tag": "ADJ"},
+        "bit": {"frequency": 0.006, "tag": "NOUN"},
+        "toy": {"frequency": 0.001, "tag": "NOUN"},
+        "rest": {"frequency": 0.007, "tag": "NOUN"},
+        "style": {"frequency": 0.004, "tag": "NOUN"},
+        "fit": {"frequency": 0.005, "tag": "ADJ"},
+        "soft": {"frequency": 0.003, "tag": "ADJ"},
+        "tube": {"frequency": 0.002, "tag": "NOUN"},
+        "tree": {"frequency": 0.006, "tag": "NOUN"}
+    }"#;
+    fn fixture_state(anagram: &str, args: &[&str]) -> State {
+        let data =
+            Data::from_json(FIXTURE, Path::new("data.json"), &WordFilter::default()).unwrap();
+        let templates = Templates::from_json(
+            br#"[["NOUN", "ADP", "NOUN"], ["VERB", "ADP", "VERB", "ADJ"]]"#,
+            Path::new("templates.json"),
+        )
+        .unwrap();
+        let argv = ["descramble", "solve", anagram]
+            .into_iter()
+            .chain(args.iter().copied());
+        let Command::Solve(solve) = Args::try_parse_from(argv).unwrap().command else {
+            panic!("expected solve");
+        };
+        let scorer = ScorerSpec::default_for(false).build(false).unwrap();
+        State::new(solve, data, templates, None, scorer)
+    }
+    /// Every sorted combination of the fixture's letter counts that spells the anagram, by brute
+    /// force, with `word_count` words or any number of them if it's 0.
+    fn exhaustive(state: &State) -> Vec<Vec<Frequency>> {
+        let anagram: Frequency = Frequency::from(state.args.anagram.as_bytes());
+        let frequencies: Vec<Frequency> = (0..state.data.word_count() as u32)
+            .map(|id| Frequency::from(state.data.word(id).as_bytes()))
+            .filter(|freq| Frequency::is_valid(&(&anagram - freq)))
+            .sorted()
+            .dedup()
+            .collect();
+        let mut found: Vec<Vec<Frequency>> = vec![];
+        let mut stack: Vec<(Vec<usize>, Frequency)> = vec![(vec![], anagram)];
+        while let Some((solution, remaining)) = stack.pop() {
+            if remaining == Frequency::default() {
+                let word_count: usize = state.args.word_count as usize;
+                if word_count == 0 || solution.len() == word_count {
+                    found.push(solution.iter().map(|idx| frequencies[*idx]).collect());
+                }
+                continue;
+            }
+            let start: usize = solution.last().copied().unwrap_or(0);
+            for (idx, freq) in frequencies.iter().enumerate().skip(start) {
+                let rest: Frequency = &remaining - freq;
+                if Frequency::is_valid(&rest) {
+                    stack.push(([solution.clone(), vec![idx]].concat(), rest));
+                }
+            }
         }
+        found.sort();
+        found
+    }
+    /// The letters of each group in `solutions`, to compare against `exhaustive`.
+    fn letters(state: &State, solutions: &Solutions) -> Vec<Vec<Frequency>> {
+        solutions
+            .solutions
+            .iter()
+            .map(|solution| {
+                solution
+                    .iter()
+                    .map(|group| state.data.group_key(*group))
+                    .collect()
+            })
+            .collect()
+    }
+    #[test]
+    fn test_threads() {
+        for args in [&[][..], &["-w", "3"]] {
+            let single = Solutions::from(&fixture_state(
+                ANAGRAM,
+                &[args, &["--threads", "1"]].concat(),
+            ));
+            let pooled = Solutions::from(&fixture_state(
+                ANAGRAM,
+                &[args, &["--threads", "4"]].concat(),
+            ));
+            assert!(!single.solutions.is_empty());
+            assert_eq!(single.solutions, pooled.solutions);
+        }
+    }
+    #[test]
+    fn test_meet_in_the_middle() {
+        for word_count in ["1", "2", "3", "4"] {
+            let state = fixture_state(ANAGRAM, &["-w", word_count]);
+            assert_eq!(
+                letters(&state, &Solutions::from(&state)),
+                exhaustive(&state)
+            );
+        }
+        assert!(!exhaustive(&fixture_state(ANAGRAM, &["-w", "3"])).is_empty());
+    }
+    #[test]
+    fn test_decompose() {
+        let state = fixture_state(ANAGRAM, &["--threads", "1"]);
+        assert_eq!(
+            letters(&state, &Solutions::from(&state)),
+            exhaustive(&state)
+        );
+        // A remainder that's been decomposed once comes from the memo after that.
+        let frequencies: Vec<Frequency> = ["of", "statue", "liberty", "astute"]
+            .iter()
+            .map(|word| Frequency::from(word.as_bytes()))
+            .sorted()
+            .collect();
+        let mut by_letter: Vec<Vec<usize>> = vec![vec![]; ALPHA_COUNT];
+        for (i, freq) in frequencies.iter().enumerate() {
+            for letter in (0..ALPHA_COUNT).filter(|letter| freq.get(*letter) > 0) {
+                by_letter[letter].push(i);
+            }
+        }
+        let (memo, stats) = (Memo::default(), SearchStats::default());
+        let budget = Budget::new(None, Arc::new(AtomicBool::new(false)));
+        let remaining = Frequency::from("statueofliberty".as_bytes());
+        let decompose = |remaining| {
+            Solutions::decompose(remaining, &frequencies, &by_letter, &memo, &stats, &budget)
+        };
+        let Decomposition::Edges(first) = decompose(&remaining) else {
+            panic!("expected edges");
+        };
+        let mut found: HashSet<Vec<usize>> = HashSet::default();
+        Solutions::expand(&first, &memo, &mut vec![], &mut found, 0, &budget);
+        assert_eq!(found.len(), 2);
+        let hits: usize = stats.cache_hits();
+        let Decomposition::Edges(second) = decompose(&remaining) else {
+            panic!("expected edges");
+        };
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(stats.cache_hits(), hits + 1);
+        // Dead ends are remembered too.
+        let dead = Frequency::from("statueofliberties".as_bytes());
+        assert!(matches!(decompose(&dead), Decomposition::Dead));
+        assert!(matches!(memo.get(&dead), Some(Decomposition::Dead)));
+    }
+    #[test]
+    fn test_rarest_letter() {
+        for anagram in [ANAGRAM, "statue of liberty", "beauty for titles", "quiet"] {
+            let state = fixture_state(anagram, &[]);
+            assert_eq!(
+                letters(&state, &Solutions::from(&state)),
+                exhaustive(&state)
+            );
+        }
+        let by_letter: Vec<Vec<usize>> = vec![vec![0, 1], vec![2], vec![0, 1, 2], vec![]];
+        let remaining = |counts: [i8; 4]| {
+            let mut remaining: Frequency = Frequency::default();
+            for (letter, count) in counts.iter().enumerate() {
+                remaining.set(letter, *count);
+            }
+            remaining
+        };
+        assert_eq!(
+            Solutions::rarest_letter(&remaining([1, 1, 1, 0]), &by_letter),
+            Some(1)
+        );
+        assert_eq!(
+            Solutions::rarest_letter(&remaining([1, 0, 2, 0]), &by_letter),
+            Some(0)
+        );
+        assert_eq!(
+            Solutions::rarest_letter(&remaining([1, 0, 0, 1]), &by_letter),
+            None
+        );
+        assert_eq!(
+            Solutions::rarest_letter(&Frequency::default(), &by_letter),
+            None
+        );
+    }
+    #[test]
+    fn test_best_first() {
+        let ranked = |args: &[&str]| -> Vec<(Vec<WordId>, f64)> {
+            let state = fixture_state(ANAGRAM, args);
+            let (ranked, _): (Vec<RankedEntry>, f64) = Solutions::from(&state).rank(&state);
+            ranked
+                .into_iter()
+                .map(|(_, phrase, score)| (phrase, score))
+                .collect()
+        };
+        for args in [&[][..], &["-w", "3"]] {
+            let every: HashMap<Vec<WordId>, f64> = ranked(&[args, &["-t", "1000"]].concat())
+                .into_iter()
+                .collect();
+            let full = ranked(&[args, &["-t", "3"]].concat());
+            let best = ranked(&[args, &["-t", "3", "--best-first"]].concat());
+            assert_eq!(full.len(), 3);
+            assert!(every.len() > full.len());
+            // Tied phrases may come in either order, the scores may not.
+            let scores = |ranked: &[(Vec<WordId>, f64)]| -> Vec<f64> {
+                ranked.iter().map(|(_, score)| *score).collect()
+            };
+            assert_eq!(scores(&best), scores(&full));
+            assert!(best.iter().all(|(phrase, score)| every[phrase] == *score));
+        }
+        // Shorter phrases than the word count asked for aren't solutions.
+        for word_count in ["1", "2", "3"] {
+            let state = fixture_state(
+                "statue of",
+                &["-w", word_count, "-t", "1000", "--best-first"],
+            );
+            assert_eq!(
+                letters(&state, &Solutions::from(&state)),
+                exhaustive(&state)
+            );
+        }
+    }
+    #[test]
+    fn test_rank_budget() {
+        let state = fixture_state(ANAGRAM, &["-t", "2"]);
+        let mut solutions = Solutions::from(&state);
+        let found: usize = solutions.solutions.len();
+        assert!(found > 2);
+        // Interrupted after the search, ranking stops once it has a full page.
+        state.interrupted.store(true, Ordering::Relaxed);
+        let (ranked, _): (Vec<RankedEntry>, f64) = solutions.rank(&state);
+        assert_eq!(ranked.len(), 2);
+        assert!(
+            matches!(solutions.ranked, Coverage::Ranked(ranked, total) if ranked < total && total == found)
+        );
     }
 }
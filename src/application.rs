@@ -1,4 +1,9 @@
-use crate::{data::{State}, algorithm::Solutions};
+use crate::{
+    algorithm::{RankedEntry, Solutions},
+    data::State,
+};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 pub struct Application {
     application_state: State,
@@ -6,12 +11,31 @@ pub struct Application {
 impl Application {
     pub fn new(application_state: State) -> Self {
         Application {
-            application_state: application_state
+            application_state: application_state,
         }
     }
     pub fn start(&self) {
-        let solutions: Solutions = Solutions::from(&self.application_state);
-        let parsed_solution: Vec<((u32, u32), String, f64)> = solutions.parse(&self.application_state);
-        Solutions::display_solution(parsed_solution);
+        // The first Ctrl-C stops the search or the ranking and keeps what was found, the second quits.
+        let interrupted: Arc<AtomicBool> = Arc::clone(&self.application_state.interrupted);
+        ctrlc::set_handler(move || {
+            if interrupted.swap(true, Ordering::Relaxed) {
+                std::process::exit(130);
+            }
+        })
+        .unwrap();
+        let mut solutions: Solutions = Solutions::from(&self.application_state);
+        if self.application_state.args.stats {
+            solutions.display_stats();
+        }
+        if self.application_state.args.explain {
+            let (ranked, total_sum): (Vec<RankedEntry>, f64) =
+                solutions.rank(&self.application_state);
+            Solutions::display_explanation(&self.application_state, &ranked, total_sum);
+        } else {
+            let parsed_solution: Vec<((u32, u32), String, f64)> =
+                solutions.parse(&self.application_state);
+            Solutions::display_solution(parsed_solution);
+        }
+        solutions.display_coverage();
     }
 }
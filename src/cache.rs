@@ -0,0 +1,453 @@
+use crate::data::{Data, Frequency, GroupId, Layer, POS_TAG_COUNT, Tag, WordId};
+use memmap2::Mmap;
+use std::fs::{File, metadata, read, rename, write};
+use std::io;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const MAGIC: &[u8; 8] = b"DSCRMIDX";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 72;
+// Where the stamp starts within the header.
+const STAMP_OFFSET: usize = 16;
+
+/// Identifies the stack of dictionary layers an index was built from.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SourceStamp {
+    // Total size of every layer.
+    pub size: u64,
+    // Every layer's modification time, in nanoseconds since the Unix epoch, hashed together.
+    pub modified: u64,
+    // Every layer's contents, hashed separately and then together.
+    pub hash: u64,
+}
+impl SourceStamp {
+    /// `settings` fingerprints whatever else decides what goes into the index, both the times
+    /// and the hashes include it.
+    pub fn new(layers: &[Layer], settings: u64) -> io::Result<Self> {
+        let modified: Vec<u64> = layers
+            .iter()
+            .map(|layer| modified(&layer.path))
+            .collect::<io::Result<Vec<u64>>>()?;
+        Ok(SourceStamp {
+            size: layers.iter().map(|layer| layer.bytes.len() as u64).sum(),
+            modified: combine(modified.into_iter().chain([settings])),
+            hash: combine(
+                layers
+                    .iter()
+                    .map(|layer| fnv1a(&layer.bytes))
+                    .chain([settings]),
+            ),
+        })
+    }
+}
+
+/// The bytes of a compiled index, either built in memory or mapped read-only from disk so that
+/// every process using the same index shares its pages.
+pub enum IndexBytes {
+    Owned(Vec<u8>),
+    Mapped(Mmap),
+}
+impl Deref for IndexBytes {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        match self {
+            IndexBytes::Owned(bytes) => bytes,
+            IndexBytes::Mapped(map) => map,
+        }
+    }
+}
+
+/// Where each section of an index lives. Every section starts 8-byte aligned, in this order:
+/// word frequencies (`f64`), word string offsets (`u32`, one more than there are words),
+/// group keys (`Frequency` lanes, sorted), group offsets into the members (`u32`, one more
+/// than there are groups), group members (`WordId`), word tags (`Tag`) and the word strings.
+/// Everything is little-endian and read in place.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    pub stamp: SourceStamp,
+    pub word_count: usize,
+    pub group_count: usize,
+    pub frequencies: usize,
+    pub string_offsets: usize,
+    pub group_keys: usize,
+    pub group_offsets: usize,
+    pub group_members: usize,
+    pub tags: usize,
+    pub strings: usize,
+    pub len: usize,
+}
+impl Layout {
+    /// `None` if the sections wouldn't fit in memory, which only a corrupted header can ask for.
+    fn new(
+        stamp: SourceStamp,
+        word_count: usize,
+        group_count: usize,
+        strings_len: usize,
+    ) -> Option<Self> {
+        let section = |start: usize, count: usize, size: usize| {
+            count
+                .checked_mul(size)
+                .and_then(|len| len.checked_add(start))
+                .and_then(|end| end.checked_next_multiple_of(8))
+        };
+        let frequencies: usize = HEADER_LEN;
+        let string_offsets: usize = section(frequencies, word_count, 8)?;
+        let group_keys: usize = section(string_offsets, word_count.checked_add(1)?, 4)?;
+        let group_offsets: usize = section(group_keys, group_count, 32)?;
+        let group_members: usize = section(group_offsets, group_count.checked_add(1)?, 4)?;
+        let tags: usize = section(group_members, word_count, 4)?;
+        let strings: usize = section(tags, word_count, 1)?;
+        Some(Layout {
+            stamp,
+            word_count,
+            group_count,
+            frequencies,
+            string_offsets,
+            group_keys,
+            group_offsets,
+            group_members,
+            tags,
+            strings,
+            len: strings.checked_add(strings_len)?,
+        })
+    }
+    /// Reads the header, `None` if this isn't an index of the current version, it's truncated or
+    /// anything in it points outside of where it should. Checked once here so that the accessors
+    /// can trust it.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < HEADER_LEN || &bytes[0..8] != MAGIC || read_u32(bytes, 8) != VERSION {
+            return None;
+        }
+        let stamp: SourceStamp = SourceStamp {
+            size: read_u64(bytes, STAMP_OFFSET),
+            modified: read_u64(bytes, STAMP_OFFSET + 8),
+            hash: read_u64(bytes, STAMP_OFFSET + 16),
+        };
+        let layout: Layout = Layout::new(
+            stamp,
+            usize::try_from(read_u64(bytes, 40)).ok()?,
+            usize::try_from(read_u64(bytes, 48)).ok()?,
+            usize::try_from(read_u64(bytes, 56)).ok()?,
+        )?;
+        (layout.len == bytes.len() && layout.is_valid(bytes)).then_some(layout)
+    }
+    /// Whether the offsets only ever grow and stay within their sections, every word is UTF-8,
+    /// every group member is a word and every tag and frequency is one the dictionary allows.
+    fn is_valid(&self, bytes: &[u8]) -> bool {
+        let strings_len: usize = self.len - self.strings;
+        let offsets =
+            |at: usize, count: usize| (0..count).map(move |i| read_u32(bytes, at + i * 4));
+        let ascending = |at: usize, count: usize, end: usize| {
+            offsets(at, count + 1)
+                .try_fold(0, |previous, offset| {
+                    (offset >= previous && offset as usize <= end).then_some(offset)
+                })
+                .is_some()
+        };
+        ascending(self.string_offsets, self.word_count, strings_len)
+            && ascending(self.group_offsets, self.group_count, self.word_count)
+            && offsets(self.group_members, self.word_count)
+                .all(|member| (member as usize) < self.word_count)
+            && bytes[self.tags..self.tags + self.word_count]
+                .iter()
+                .all(|tag| (*tag as usize) < POS_TAG_COUNT)
+            && (0..self.word_count).all(|id| {
+                let frequency: f64 = self.frequency(bytes, id as WordId);
+                frequency.is_finite() && frequency >= 0.0
+            })
+            && (0..self.word_count as WordId)
+                .all(|id| std::str::from_utf8(self.word_bytes(bytes, id)).is_ok())
+    }
+    pub fn word<'a>(&self, bytes: &'a [u8], id: WordId) -> &'a str {
+        std::str::from_utf8(self.word_bytes(bytes, id)).unwrap_or_default()
+    }
+    fn word_bytes<'a>(&self, bytes: &'a [u8], id: WordId) -> &'a [u8] {
+        let start: usize = read_u32(bytes, self.string_offsets + id as usize * 4) as usize;
+        let end: usize = read_u32(bytes, self.string_offsets + (id as usize + 1) * 4) as usize;
+        &bytes[self.strings + start..self.strings + end]
+    }
+    pub fn frequency(&self, bytes: &[u8], id: WordId) -> f64 {
+        f64::from_bits(read_u64(bytes, self.frequencies + id as usize * 8))
+    }
+    pub fn tag(&self, bytes: &[u8], id: WordId) -> Tag {
+        bytes[self.tags + id as usize]
+    }
+    pub fn group_key(&self, bytes: &[u8], group: GroupId) -> Frequency {
+        let mut lanes: [u64; 4] = [0; 4];
+        for (lane, word) in lanes.iter_mut().enumerate() {
+            *word = read_u64(bytes, self.group_keys + group as usize * 32 + lane * 8);
+        }
+        Frequency::from_lanes(lanes)
+    }
+    /// The members of `group`.
+    pub fn group<'a>(
+        &self,
+        bytes: &'a [u8],
+        group: GroupId,
+    ) -> impl ExactSizeIterator<Item = WordId> + use<'a> {
+        let group: usize = group as usize;
+        let members: std::ops::Range<usize> = read_u32(bytes, self.group_offsets + group * 4)
+            as usize
+            ..read_u32(bytes, self.group_offsets + (group + 1) * 4) as usize;
+        let at: usize = self.group_members;
+        members.map(move |member| read_u32(bytes, at + member * 4))
+    }
+}
+
+/// The compiled index is written next to the top layer it was built from. A lone `data.json`
+/// gets `data.idx`, a stack gets a name that depends on every layer in it.
+pub fn index_path(sources: &[PathBuf]) -> PathBuf {
+    let top: &PathBuf = sources.last().expect("at least one dictionary layer");
+    match sources {
+        [_] => top.with_extension("idx"),
+        _ => {
+            let paths: Vec<u8> = sources
+                .iter()
+                .flat_map(|source| {
+                    source
+                        .to_string_lossy()
+                        .into_owned()
+                        .into_bytes()
+                        .into_iter()
+                        .chain([0])
+                })
+                .collect();
+            top.with_extension(format!("{:016x}.idx", fnv1a(&paths)))
+        }
+    }
+}
+/// Maps the index at `index` if it was built from `sources` as they are now, with the same
+/// `settings`. The sizes and modification times are checked first, the hashes only when a file
+/// was touched.
+pub fn load(sources: &[PathBuf], index: &Path, settings: u64) -> Option<Data> {
+    let file: File = File::open(index).ok()?;
+    // SAFETY: Indexes are only ever replaced by renaming a new file over them, never written in
+    // place, so the mapped file can't change underneath us.
+    let map: Mmap = unsafe { Mmap::map(&file) }.ok()?;
+    let layout: Layout = Layout::parse(&map)?;
+    let size: u64 = sources
+        .iter()
+        .map(|source| metadata(source).map(|metadata| metadata.len()))
+        .sum::<io::Result<u64>>()
+        .ok()?;
+    if layout.stamp.size != size {
+        return None;
+    }
+    let modified: Vec<u64> = sources
+        .iter()
+        .map(|source| modified(source))
+        .collect::<io::Result<Vec<u64>>>()
+        .ok()?;
+    let modified: u64 = combine(modified.into_iter().chain([settings]));
+    if layout.stamp.modified == modified {
+        return Some(Data::from_index(IndexBytes::Mapped(map), layout));
+    }
+    let hashes: Vec<u64> = sources
+        .iter()
+        .map(|source| read(source).map(|bytes| fnv1a(&bytes)))
+        .collect::<io::Result<Vec<u64>>>()
+        .ok()?;
+    if layout.stamp.hash != combine(hashes.into_iter().chain([settings])) {
+        return None;
+    }
+    // Touched but unchanged. Restamping spares later runs the hashing, if the index is writable.
+    let stamp: SourceStamp = SourceStamp {
+        modified,
+        ..layout.stamp
+    };
+    let data: Data = Data::from_index(IndexBytes::Mapped(map), layout);
+    let _ = store(&data, &stamp, index);
+    Some(data)
+}
+/// Writes `data` to `index` stamped with its source. Goes through a temporary file and a rename so
+/// that processes still mapping the old index aren't affected.
+pub fn store(data: &Data, stamp: &SourceStamp, index: &Path) -> io::Result<()> {
+    let mut bytes: Vec<u8> = data.bytes().to_vec();
+    for (offset, value) in [stamp.size, stamp.modified, stamp.hash].iter().enumerate() {
+        let at: usize = STAMP_OFFSET + offset * 8;
+        bytes[at..at + 8].copy_from_slice(&value.to_le_bytes());
+    }
+    let temporary = index.with_extension(format!("idx.{}", std::process::id()));
+    write(&temporary, bytes)?;
+    rename(&temporary, index)
+}
+/// Compiles a word table into an index. `groups` must be sorted by key.
+pub fn encode(
+    words: &[String],
+    frequencies: &[f64],
+    tags: &[Tag],
+    groups: &[(Frequency, Vec<WordId>)],
+) -> (Vec<u8>, Layout) {
+    let strings_len: usize = words.iter().map(|word| word.len()).sum();
+    let layout: Layout = Layout::new(
+        SourceStamp::default(),
+        words.len(),
+        groups.len(),
+        strings_len,
+    )
+    .expect("the index fits in memory");
+    let mut bytes: Vec<u8> = vec![0; layout.len];
+    bytes[0..8].copy_from_slice(MAGIC);
+    bytes[8..12].copy_from_slice(&VERSION.to_le_bytes());
+    for (offset, value) in [words.len(), groups.len(), strings_len].iter().enumerate() {
+        let at: usize = 40 + offset * 8;
+        bytes[at..at + 8].copy_from_slice(&(*value as u64).to_le_bytes());
+    }
+    let mut string_offset: usize = 0;
+    for (id, word) in words.iter().enumerate() {
+        let at: usize = layout.frequencies + id * 8;
+        bytes[at..at + 8].copy_from_slice(&frequencies[id].to_le_bytes());
+        let at: usize = layout.string_offsets + id * 4;
+        bytes[at..at + 4].copy_from_slice(&(string_offset as u32).to_le_bytes());
+        bytes[layout.tags + id] = tags[id];
+        let at: usize = layout.strings + string_offset;
+        bytes[at..at + word.len()].copy_from_slice(word.as_bytes());
+        string_offset += word.len();
+    }
+    let at: usize = layout.string_offsets + words.len() * 4;
+    bytes[at..at + 4].copy_from_slice(&(string_offset as u32).to_le_bytes());
+    let mut member_offset: usize = 0;
+    for (group, (key, members)) in groups.iter().enumerate() {
+        for (lane, word) in key.lanes().iter().enumerate() {
+            let at: usize = layout.group_keys + group * 32 + lane * 8;
+            bytes[at..at + 8].copy_from_slice(&word.to_le_bytes());
+        }
+        let at: usize = layout.group_offsets + group * 4;
+        bytes[at..at + 4].copy_from_slice(&(member_offset as u32).to_le_bytes());
+        for member in members.iter() {
+            let at: usize = layout.group_members + member_offset * 4;
+            bytes[at..at + 4].copy_from_slice(&member.to_le_bytes());
+            member_offset += 1;
+        }
+    }
+    let at: usize = layout.group_offsets + groups.len() * 4;
+    bytes[at..at + 4].copy_from_slice(&(member_offset as u32).to_le_bytes());
+    (bytes, layout)
+}
+/// 64-bit FNV-1a.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+/// Hashes a sequence of values into one.
+fn combine(values: impl IntoIterator<Item = u64>) -> u64 {
+    let bytes: Vec<u8> = values.into_iter().flat_map(u64::to_le_bytes).collect();
+    fnv1a(&bytes)
+}
+fn modified(path: &Path) -> io::Result<u64> {
+    Ok(metadata(path)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_nanos() as u64)
+        .unwrap_or_default())
+}
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cache::{IndexBytes, Layout, SourceStamp, index_path, store};
+    use crate::data::{Data, Frequency, GroupId, WordId};
+    use crate::words::WordFilter;
+    use std::fs::File;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, SystemTime};
+    #[test]
+    fn test_restamp() {
+        let directory: PathBuf =
+            std::env::temp_dir().join(format!("descramble-restamp-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let source: PathBuf = directory.join("data.json");
+        std::fs::write(
+            &source,
+            br#"{"statue": {"frequency": 2e-6, "tag": "NOUN"}}"#,
+        )
+        .unwrap();
+        let sources: Vec<PathBuf> = vec![source.clone()];
+        let stamp = || {
+            Layout::parse(&std::fs::read(index_path(&sources)).unwrap())
+                .unwrap()
+                .stamp
+        };
+        Data::load(&sources, &WordFilter::default()).unwrap();
+        let built: SourceStamp = stamp();
+        // Touching the file without changing it is caught by the hash and the stamp refreshed.
+        File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        Data::load(&sources, &WordFilter::default()).unwrap();
+        let refreshed: SourceStamp = stamp();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_ne!(refreshed.modified, built.modified);
+        assert_eq!(refreshed.hash, built.hash);
+        assert_eq!(refreshed.size, built.size);
+    }
+    #[test]
+    fn test_index_round_trip() {
+        let json = br#"{
+            "statue": {"frequency": 2e-6, "tag": "NOUN"},
+            "astute": {"frequency": 1e-6, "tag": "ADJ"},
+            "of": {"frequency": 3e-2, "tag": "ADP"},
+            "Liberty": {"frequency": 1e-5, "tag": "PROPN"}
+        }"#;
+        let data = Data::from_json(json, Path::new("data.json"), &WordFilter::default()).unwrap();
+        let stamp = SourceStamp {
+            size: json.len() as u64,
+            modified: 1,
+            hash: 2,
+        };
+        let path = std::env::temp_dir().join(format!("descramble-{}.idx", std::process::id()));
+        store(&data, &stamp, &path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let layout = Layout::parse(&bytes).unwrap();
+        assert_eq!(layout.stamp, stamp);
+        assert!(Layout::parse(&bytes[..bytes.len() - 1]).is_none());
+        // Corrupted tables of the right length are turned away rather than trusted.
+        let corrupt = |at: usize, value: &[u8]| {
+            let mut corrupted: Vec<u8> = bytes.clone();
+            corrupted[at..at + value.len()].copy_from_slice(value);
+            Layout::parse(&corrupted).is_none()
+        };
+        assert!(corrupt(layout.string_offsets + 4, &u32::MAX.to_le_bytes()));
+        assert!(corrupt(layout.group_offsets + 4, &100u32.to_le_bytes()));
+        assert!(corrupt(layout.group_members, &7u32.to_le_bytes()));
+        assert!(corrupt(layout.tags, &[200]));
+        assert!(corrupt(layout.frequencies, &f64::NAN.to_le_bytes()));
+        assert!(corrupt(layout.strings, &[0xff]));
+        assert!(corrupt(40, &u64::MAX.to_le_bytes()));
+        let decoded = Data::from_index(IndexBytes::Owned(bytes), layout);
+        assert_eq!(decoded.word_count(), 3);
+        let key: Frequency = Frequency::from("statue".as_bytes());
+        let statue: GroupId = decoded
+            .index
+            .sub_multisets_of(&key)
+            .into_iter()
+            .find(|group| decoded.group_key(*group) == key)
+            .unwrap();
+        let group: Vec<WordId> = decoded.group(statue).collect();
+        let words: Vec<&str> = group.iter().map(|id| decoded.word(*id)).collect();
+        assert_eq!(words, ["astute", "statue"]);
+        assert_eq!(decoded.frequency(group[1]), 2e-6);
+        assert_eq!(decoded.tag(group[0]), data.tag(group[0]));
+        assert!(
+            decoded
+                .index
+                .sub_multisets_of(&Frequency::from("xyz".as_bytes()))
+                .is_empty()
+        );
+    }
+}
@@ -1,17 +1,60 @@
-use clap::Parser;
-use serde::Deserialize;
+use crate::algorithm::Templates;
+use crate::cache::{self, IndexBytes, Layout, SourceStamp};
+use crate::index::AnagramIndex;
+use crate::ngram::LanguageModel;
+use crate::scorer::{Scorer, ScorerSpec, parse_spec};
+use crate::words::{LANGUAGES, WordFilter};
+use clap::{Parser, Subcommand};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 use serde_json::{self};
 use std::cmp::PartialEq;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::fs::read;
+use std::io;
 use std::ops::{Add, Sub};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 
-const ALPHA_COUNT: usize = 26;
+pub const ALPHA_COUNT: usize = 26;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
+    #[arg(long, global = true, env = "DESCRAMBLE_DATA")]
+    pub data_dir: Option<PathBuf>,
+    #[arg(long, global = true)]
+    pub dict: Vec<PathBuf>,
+    #[arg(long, global = true)]
+    pub templates: Option<PathBuf>,
+    #[arg(long, global = true)]
+    pub short_words: Option<PathBuf>,
+    #[arg(long, global = true, value_parser = LANGUAGES)]
+    pub language: Option<String>,
+    #[arg(long, global = true)]
+    pub min_length: Option<usize>,
+    #[command(subcommand)]
+    pub command: Command,
+}
+#[derive(Subcommand)]
+pub enum Command {
+    /// Find and rank the phrases an anagram can be rearranged into.
+    Solve(SolveArgs),
+    /// Manage the compiled dictionary index.
+    Index {
+        #[command(subcommand)]
+        action: IndexAction,
+    },
+    /// Build and maintain dictionaries.
+    Dict {
+        #[command(subcommand)]
+        action: DictAction,
+    },
+}
+#[derive(clap::Args)]
+pub struct SolveArgs {
     pub anagram: String,
     #[arg(short, long, default_value_t = 0)]
     pub word_count: u8,
@@ -19,35 +62,145 @@ pub struct Args {
     pub top_results: u32,
     #[arg(short, long, default_value_t = 0)]
     pub strength: u8,
+    #[arg(long, default_value_t = 0)]
+    pub threads: usize,
+    #[arg(long)]
+    pub stats: bool,
+    #[arg(long)]
+    pub best_first: bool,
+    #[arg(long, value_parser = parse_non_negative)]
+    pub time_limit: Option<f64>,
+    #[arg(long)]
+    pub model: Option<PathBuf>,
+    #[arg(long, value_parser = parse_spec)]
+    pub scorer: Option<ScorerSpec>,
+    #[arg(long)]
+    pub explain: bool,
+}
+#[derive(Subcommand)]
+pub enum IndexAction {
+    /// Recompile the index of the dictionary.
+    Rebuild,
+}
+#[derive(Subcommand)]
+pub enum DictAction {
+    /// Convert a word list, or `word<TAB>count[<TAB>tag]` lines, into a dictionary.
+    ///
+    /// Frequencies are each word's share of the counts of the words that are kept, lines dropped
+    /// for not being usable words don't count towards the total.
+    Import {
+        input: PathBuf,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[arg(long, default_value = "NOUN", value_parser = POS_TAGS)]
+        default_tag: String,
+    },
+    /// Count the words of the text files in a directory into a dictionary.
+    FromCorpus {
+        corpus: PathBuf,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[arg(long)]
+        base: Option<PathBuf>,
+        #[arg(long, default_value_t = 0.5, value_parser = parse_weight)]
+        weight: f64,
+        #[arg(long, default_value_t = 1.0, value_parser = parse_non_negative)]
+        smoothing: f64,
+        #[arg(long, default_value = "NOUN", value_parser = POS_TAGS)]
+        default_tag: String,
+    },
+    /// Report problems with the dictionary and statistics about it.
+    Check,
+}
+fn parse_weight(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(weight) if (0.0..=1.0).contains(&weight) => Ok(weight),
+        _ => Err(String::from("expected a number from 0 to 1")),
+    }
+}
+fn parse_non_negative(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(value) if value.is_finite() && value >= 0.0 => Ok(value),
+        _ => Err(String::from("expected a number no less than 0")),
+    }
 }
 
 pub struct State {
-    pub args: Args,
+    pub args: SolveArgs,
     pub data: Data,
-    pub root_path: PathBuf,
+    pub templates: Templates,
+    pub model: Option<LanguageModel>,
+    pub scorer: Box<dyn Scorer>,
+    // Set when the user asks to stop the search early.
+    pub interrupted: Arc<AtomicBool>,
 }
 impl State {
-    pub fn new(args: Args, data: Data, root_path: PathBuf) -> Self {
+    pub fn new(
+        args: SolveArgs,
+        data: Data,
+        templates: Templates,
+        model: Option<LanguageModel>,
+        scorer: Box<dyn Scorer>,
+    ) -> Self {
         State {
-            args: args,
-            data: data,
-            root_path: root_path,
+            args,
+            data,
+            templates,
+            model,
+            scorer,
+            interrupted: Arc::new(AtomicBool::new(false)),
         }
     }
 }
 
-#[derive(Default, Eq, Hash, Debug, Clone, Copy)]
+const LANE_WORDS: usize = 4;
+const LANES_PER_WORD: usize = 8;
+// Sign bit of every 8-bit lane.
+const SIGN_BITS: u64 = 0x8080_8080_8080_8080;
+
+/// Letter counts packed as signed 8-bit lanes, eight to a word, so that addition, subtraction
+/// and the sign check work on whole words at once. Lanes past the alphabet stay zero.
+#[derive(Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
 pub struct Frequency {
-    pub arr: [i8; ALPHA_COUNT],
+    lanes: [u64; LANE_WORDS],
 }
 impl Frequency {
+    pub fn from_lanes(lanes: [u64; LANE_WORDS]) -> Self {
+        Frequency { lanes }
+    }
+    pub fn lanes(&self) -> [u64; LANE_WORDS] {
+        self.lanes
+    }
     pub fn is_valid(freqeuncy: &Frequency) -> bool {
-        for val in freqeuncy.arr {
-            if val < 0 {
-                return false;
-            }
+        let mut signs: u64 = 0;
+        for word in freqeuncy.lanes {
+            signs |= word;
+        }
+        signs & SIGN_BITS == 0
+    }
+    pub fn get(&self, letter: usize) -> i8 {
+        (self.lanes[letter / LANES_PER_WORD] >> (8 * (letter % LANES_PER_WORD))) as u8 as i8
+    }
+    pub fn set(&mut self, letter: usize, value: i8) {
+        let shift: usize = 8 * (letter % LANES_PER_WORD);
+        let word: &mut u64 = &mut self.lanes[letter / LANES_PER_WORD];
+        *word = (*word & !(0xFF << shift)) | ((value as u8 as u64) << shift);
+    }
+    pub fn arr(&self) -> [i8; ALPHA_COUNT] {
+        let mut data: [i8; ALPHA_COUNT] = [0; ALPHA_COUNT];
+        for (letter, val) in data.iter_mut().enumerate() {
+            *val = self.get(letter);
+        }
+        data
+    }
+}
+impl From<[i8; ALPHA_COUNT]> for Frequency {
+    fn from(data: [i8; ALPHA_COUNT]) -> Self {
+        let mut frequency: Frequency = Frequency::default();
+        for (letter, val) in data.iter().enumerate() {
+            frequency.set(letter, *val);
         }
-        true
+        frequency
     }
 }
 impl From<&[u8]> for Frequency {
@@ -59,94 +212,448 @@ impl From<&[u8]> for Frequency {
                 data[(val - b'a') as usize] += 1;
             }
         }
-        Frequency { arr: data }
+        Frequency::from(data)
     }
 }
 impl From<i8> for Frequency {
     fn from(value: i8) -> Self {
-        let mut data: [i8; ALPHA_COUNT] = [0; ALPHA_COUNT];
-        for val in data.iter_mut() {
-            *val = value;
-        }
-        Frequency { arr: data }
+        Frequency::from([value; ALPHA_COUNT])
     }
 }
 impl<'a, 'b> Add<&'b Frequency> for &'a Frequency {
     type Output = Frequency;
     fn add(self, other: &'b Frequency) -> Frequency {
-        let mut result: [i8; ALPHA_COUNT] = [0; ALPHA_COUNT];
-        for i in 0..ALPHA_COUNT {
-            result[i] = self.arr[i] + other.arr[i];
+        let mut lanes: [u64; LANE_WORDS] = [0; LANE_WORDS];
+        for (i, word) in lanes.iter_mut().enumerate() {
+            let (a, b): (u64, u64) = (self.lanes[i], other.lanes[i]);
+            // Add the low 7 bits of every lane, then fix up the sign bits so no carry crosses lanes.
+            *word = ((a & !SIGN_BITS) + (b & !SIGN_BITS)) ^ ((a ^ b) & SIGN_BITS);
         }
-        Frequency { arr: result }
+        Frequency { lanes }
     }
 }
 impl<'a, 'b> Sub<&'b Frequency> for &'a Frequency {
     type Output = Frequency;
     fn sub(self, other: &'b Frequency) -> Frequency {
-        let mut result: [i8; ALPHA_COUNT] = [0; ALPHA_COUNT];
-        for i in 0..ALPHA_COUNT {
-            result[i] = self.arr[i] - other.arr[i];
+        let mut lanes: [u64; LANE_WORDS] = [0; LANE_WORDS];
+        for (i, word) in lanes.iter_mut().enumerate() {
+            let (a, b): (u64, u64) = (self.lanes[i], other.lanes[i]);
+            // Borrow from a set sign bit in every lane, then fix up the sign bits.
+            *word = ((a | SIGN_BITS) - (b & !SIGN_BITS)) ^ ((a ^ !b) & SIGN_BITS);
         }
-        Frequency { arr: result }
+        Frequency { lanes }
     }
 }
-impl PartialEq for Frequency {
-    fn eq(&self, other: &Frequency) -> bool {
-        self.arr == other.arr
-    }
-}
-#[derive(Debug, Deserialize)]
+/// A dictionary entry. Overlays may leave out the fields they don't change, or remove the word.
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Entry {
-    pub frequency: f64,
-    pub tag: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub remove: bool,
+}
+/// One dictionary file of a stack, read whole.
+pub struct Layer {
+    pub path: PathBuf,
+    pub bytes: Vec<u8>,
 }
+/// Why the dictionary or the templates couldn't be loaded.
 #[derive(Debug)]
 pub enum DataError {
-    // ParseError(std::string::FromUtf8Error),
-    // IOError(std::io::Error),
+    Missing(PathBuf),
+    NotFound {
+        file: String,
+        searched: Vec<PathBuf>,
+    },
+    Io(PathBuf, io::Error),
+    Json(PathBuf, serde_json::Error),
+    UnknownTag {
+        path: PathBuf,
+        entry: String,
+        tag: String,
+    },
+    NonAlphabetic {
+        path: PathBuf,
+        word: String,
+    },
+    InvalidFrequency {
+        path: PathBuf,
+        word: String,
+        frequency: f64,
+    },
+    Pattern {
+        path: PathBuf,
+        pattern: String,
+        reason: String,
+    },
+    Incomplete {
+        path: PathBuf,
+        word: String,
+    },
+    Malformed {
+        path: PathBuf,
+        line: usize,
+        reason: String,
+    },
+}
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DataError::Missing(path) => write!(f, "{} not found", path.display()),
+            DataError::NotFound { file, searched } => {
+                write!(f, "{} not found, searched:", file)?;
+                for path in searched {
+                    write!(f, "\n  {}", path.display())?;
+                }
+                write!(
+                    f,
+                    "\nUse --data-dir or DESCRAMBLE_DATA to point at a data directory."
+                )
+            }
+            DataError::Io(path, err) => write!(f, "cannot read {}: {}", path.display(), err),
+            // serde_json already reports the line and column.
+            DataError::Json(path, err) => {
+                write!(f, "{}: malformed JSON, {}", path.display(), err)
+            }
+            DataError::UnknownTag { path, entry, tag } => write!(
+                f,
+                "{}: unknown POS tag \"{}\" in \"{}\", expected one of {}",
+                path.display(),
+                tag,
+                entry,
+                POS_TAGS.join(", ")
+            ),
+            DataError::NonAlphabetic { path, word } => write!(
+                f,
+                "{}: \"{}\" contains characters other than letters, apostrophes and hyphens",
+                path.display(),
+                word
+            ),
+            DataError::InvalidFrequency {
+                path,
+                word,
+                frequency,
+            } => write!(
+                f,
+                "{}: \"{}\" has frequency {}, expected a finite number no less than 0",
+                path.display(),
+                word,
+                frequency
+            ),
+            DataError::Pattern {
+                path,
+                pattern,
+                reason,
+            } => write!(f, "{}: \"{}\": {}", path.display(), pattern, reason),
+            DataError::Incomplete { path, word } => write!(
+                f,
+                "{}: \"{}\" isn't in any layer below, so it needs both a frequency and a tag",
+                path.display(),
+                word
+            ),
+            DataError::Malformed { path, line, reason } => {
+                write!(f, "{}:{}: {}", path.display(), line, reason)
+            }
+        }
+    }
+}
+impl std::error::Error for DataError {}
+/// Letters, plus the apostrophes and hyphens of contractions and compounds.
+fn is_word_char(char: char) -> bool {
+    char.is_alphabetic() || char == '\'' || char == '-'
+}
+/// Reads `path` whole, telling a missing file apart from one that can't be read.
+pub fn read_file(path: &Path) -> Result<Vec<u8>, DataError> {
+    read(path).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => DataError::Missing(path.to_path_buf()),
+        _ => DataError::Io(path.to_path_buf(), err),
+    })
+}
+/// Index of a word in the `Data` word table.
+pub type WordId = u32;
+/// Index of a group of words spelled with the same letters, in order of their `Frequency`.
+pub type GroupId = u32;
+/// Index of a POS tag in `POS_TAGS`.
+pub type Tag = u8;
+pub const POS_TAG_COUNT: usize = 14;
+pub const POS_TAGS: [&str; POS_TAG_COUNT] = [
+    "ADJ", "ADP", "ADV", "AUX", "CCONJ", "DET", "INTJ", "NOUN", "NUM", "PART", "PRON", "PROPN",
+    "SCONJ", "VERB",
+];
+pub fn tag_index(tag: &str) -> Option<Tag> {
+    POS_TAGS
+        .iter()
+        .position(|other| *other == tag)
+        .map(|idx| idx as Tag)
 }
+/// Dictionary words interned as `WordId`s, with their frequency and tag stored alongside. Reads
+/// straight out of a compiled index, which is usually mapped from disk rather than copied in.
 pub struct Data {
-    pub string_mapping: HashMap<Frequency, Vec<String>>,
-    pub string_data: HashMap<String, Entry>,
-}
-/// Get dictionary data from a specified path.
-impl TryFrom<&PathBuf> for Data {
-    type Error = DataError;
-    fn try_from(path: &PathBuf) -> Result<Self, Self::Error> {
-        let data: HashMap<String, Entry> =
-            serde_json::from_slice(&read(path.join("data.json")).unwrap()).unwrap();
-        let mut mappings: HashMap<Frequency, Vec<String>> = HashMap::default();
-        let valid_short_strings: HashSet<&str> = HashSet::from([
-            "a", "i", "am", "an", "as", "at", "be", "by", "do", "he", "hi", "if", "in", "is", "it",
-            "me", "my", "no", "of", "oh", "on", "or", "ox", "so", "to", "up", "us",
-        ]);
-        'main: for string in data.keys() {
-            for char in string.as_bytes() {
-                if !(b'a' <= *char && *char <= b'z') {
-                    continue 'main;
+    bytes: IndexBytes,
+    layout: Layout,
+    pub index: AnagramIndex,
+}
+impl Data {
+    pub fn word(&self, id: WordId) -> &str {
+        self.layout.word(&self.bytes, id)
+    }
+    pub fn frequency(&self, id: WordId) -> f64 {
+        self.layout.frequency(&self.bytes, id)
+    }
+    pub fn tag(&self, id: WordId) -> Tag {
+        self.layout.tag(&self.bytes, id)
+    }
+    /// Every word in `group`.
+    pub fn group(&self, group: GroupId) -> impl ExactSizeIterator<Item = WordId> + '_ {
+        self.layout.group(&self.bytes, group)
+    }
+    /// The letters every word in `group` is made up of.
+    pub fn group_key(&self, group: GroupId) -> Frequency {
+        self.layout.group_key(&self.bytes, group)
+    }
+    pub fn word_count(&self) -> usize {
+        self.layout.word_count
+    }
+    /// The compiled index backing this table.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+impl Data {
+    pub fn new(
+        words: Vec<String>,
+        frequencies: Vec<f64>,
+        tags: Vec<Tag>,
+        string_mapping: HashMap<Frequency, Vec<WordId>>,
+    ) -> Self {
+        let groups: Vec<(Frequency, Vec<WordId>)> = string_mapping.into_iter().sorted().collect();
+        let (bytes, layout): (Vec<u8>, Layout) =
+            cache::encode(&words, &frequencies, &tags, &groups);
+        Data::from_index(IndexBytes::Owned(bytes), layout)
+    }
+    /// Wraps a compiled index. Only the anagram index is built up front.
+    pub fn from_index(bytes: IndexBytes, layout: Layout) -> Self {
+        let keys: Vec<Frequency> = (0..layout.group_count)
+            .map(|group| layout.group_key(&bytes, group as GroupId))
+            .collect();
+        Data {
+            index: AnagramIndex::new(keys.iter()),
+            bytes,
+            layout,
+        }
+    }
+    /// Builds the word table from the contents of a `data.json`, `source` only names it in errors.
+    pub fn from_json(bytes: &[u8], source: &Path, filter: &WordFilter) -> Result<Self, DataError> {
+        Data::from_layers(
+            &[Layer {
+                path: source.to_path_buf(),
+                bytes: bytes.to_vec(),
+            }],
+            filter,
+        )
+    }
+    /// Builds the word table from a stack of dictionaries, each layer adding to, overriding or
+    /// removing entries of the ones below it.
+    pub fn from_layers(layers: &[Layer], filter: &WordFilter) -> Result<Self, DataError> {
+        let merged: BTreeMap<String, (f64, Tag)> = Data::merge_layers(layers)?;
+        let mut words: Vec<String> = vec![];
+        let mut frequencies: Vec<f64> = vec![];
+        let mut tags: Vec<Tag> = vec![];
+        let mut mappings: HashMap<Frequency, Vec<WordId>> = HashMap::default();
+        // Sorted so that ids don't depend on hashing.
+        for (string, (frequency, tag)) in merged {
+            if !filter.is_usable(&string) {
+                continue;
+            }
+            let id: WordId = words.len() as WordId;
+            mappings
+                .entry(Frequency::from(string.as_bytes()))
+                .or_default()
+                .push(id);
+            words.push(string);
+            frequencies.push(frequency);
+            tags.push(tag);
+        }
+        Ok(Data::new(words, frequencies, tags, mappings))
+    }
+    /// Every entry of a dictionary stack, usable in anagrams or not, with its frequency and tag.
+    pub fn merge_layers(layers: &[Layer]) -> Result<BTreeMap<String, (f64, Tag)>, DataError> {
+        let mut merged: BTreeMap<String, (f64, Tag)> = BTreeMap::new();
+        for layer in layers {
+            Data::apply_layer(&mut merged, layer)?;
+        }
+        Ok(merged)
+    }
+    fn apply_layer(
+        merged: &mut BTreeMap<String, (f64, Tag)>,
+        layer: &Layer,
+    ) -> Result<(), DataError> {
+        // Sorted so that the first bad entry is the one reported.
+        for (string, entry) in Data::parse_layer(layer)? {
+            Data::apply_entry(merged, &layer.path, string, entry)?;
+        }
+        Ok(())
+    }
+    /// The entries of a layer, sorted by word.
+    pub fn parse_layer(layer: &Layer) -> Result<Vec<(String, Entry)>, DataError> {
+        let entries: HashMap<String, Entry> = serde_json::from_slice(&layer.bytes)
+            .map_err(|err| DataError::Json(layer.path.to_path_buf(), err))?;
+        Ok(entries
+            .into_iter()
+            .sorted_by(|a, b| a.0.cmp(&b.0))
+            .collect())
+    }
+    /// Adds, overrides or removes one entry of the layer at `path`.
+    pub fn apply_entry(
+        merged: &mut BTreeMap<String, (f64, Tag)>,
+        path: &Path,
+        string: String,
+        entry: Entry,
+    ) -> Result<(), DataError> {
+        if !string.chars().all(is_word_char) {
+            return Err(DataError::NonAlphabetic {
+                path: path.to_path_buf(),
+                word: string,
+            });
+        }
+        if entry.remove {
+            merged.remove(&string);
+            return Ok(());
+        }
+        if let Some(frequency) = entry.frequency
+            && (!frequency.is_finite() || frequency < 0.0)
+        {
+            return Err(DataError::InvalidFrequency {
+                path: path.to_path_buf(),
+                word: string,
+                frequency,
+            });
+        }
+        let tag: Option<Tag> = match entry.tag {
+            Some(tag) => match tag_index(&tag) {
+                Some(tag) => Some(tag),
+                None => {
+                    return Err(DataError::UnknownTag {
+                        path: path.to_path_buf(),
+                        entry: string,
+                        tag,
+                    });
                 }
+            },
+            None => None,
+        };
+        match (merged.get_mut(&string), entry.frequency, tag) {
+            // Overrides whichever fields the layer gives.
+            (Some(existing), frequency, tag) => {
+                existing.0 = frequency.unwrap_or(existing.0);
+                existing.1 = tag.unwrap_or(existing.1);
             }
-            if string.len() < 3 && !valid_short_strings.contains(string.as_str()) {
-                continue 'main;
+            (None, Some(frequency), Some(tag)) => {
+                merged.insert(string, (frequency, tag));
             }
-            let frequency = Frequency::from(string.as_bytes());
-            if mappings.contains_key(&frequency) {
-                mappings.get_mut(&frequency).unwrap().push(string.clone());
-            } else {
-                mappings.insert(frequency, vec![string.clone()]);
+            (None, _, _) => {
+                return Err(DataError::Incomplete {
+                    path: path.to_path_buf(),
+                    word: string,
+                });
             }
         }
-        Ok(Data {
-            string_mapping: mappings,
-            string_data: data,
-        })
+        Ok(())
+    }
+    /// Reads every layer of a dictionary stack.
+    pub fn read_layers(sources: &[PathBuf]) -> Result<Vec<Layer>, DataError> {
+        sources
+            .iter()
+            .map(|source| {
+                Ok(Layer {
+                    path: source.clone(),
+                    bytes: read_file(source)?,
+                })
+            })
+            .collect()
+    }
+    /// Recompiles the index of the dictionary stack `sources` regardless of whether it's stale.
+    pub fn rebuild_index(sources: &[PathBuf], filter: &WordFilter) -> Result<Self, DataError> {
+        let index: PathBuf = cache::index_path(sources);
+        let layers: Vec<Layer> = Data::read_layers(sources)?;
+        let data: Data = Data::from_layers(&layers, filter)?;
+        SourceStamp::new(&layers, filter.fingerprint())
+            .and_then(|stamp| cache::store(&data, &stamp, &index))
+            .map_err(|err| DataError::Io(index, err))?;
+        Ok(data)
+    }
+    /// Get dictionary data from a stack of `data.json`s, base first. Uses the compiled index next
+    /// to the top one when it's up to date and was built with the same filter, otherwise parses
+    /// the JSON and writes a fresh index for next time.
+    pub fn load(sources: &[PathBuf], filter: &WordFilter) -> Result<Self, DataError> {
+        let index: PathBuf = cache::index_path(sources);
+        if let Some(data) = cache::load(sources, &index, filter.fingerprint()) {
+            return Ok(data);
+        }
+        let layers: Vec<Layer> = Data::read_layers(sources)?;
+        let data: Data = Data::from_layers(&layers, filter)?;
+        // The data directory may well be read-only, the index is only an optimization.
+        if let Ok(stamp) = SourceStamp::new(&layers, filter.fingerprint()) {
+            let _ = cache::store(&data, &stamp, &index);
+        }
+        Ok(data)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::data::Frequency;
+    use crate::data::{
+        Args, Command, Data, DataError, DictAction, Frequency, GroupId, IndexAction, Layer,
+        tag_index,
+    };
+    use crate::words::WordFilter;
+    use clap::Parser;
+    use std::path::{Path, PathBuf};
+    #[test]
+    fn test_args() {
+        // Words that are also command names can still be solved.
+        match Args::try_parse_from(["descramble", "solve", "index", "-w", "2"])
+            .unwrap()
+            .command
+        {
+            Command::Solve(solve) => {
+                assert_eq!(solve.anagram, "index");
+                assert_eq!(solve.word_count, 2);
+            }
+            _ => panic!("expected solve"),
+        }
+        match Args::try_parse_from(["descramble", "solve", "dict", "--dict", "words.json"]) {
+            Ok(Args {
+                command: Command::Solve(solve),
+                dict,
+                ..
+            }) => {
+                assert_eq!(solve.anagram, "dict");
+                assert_eq!(dict, [PathBuf::from("words.json")]);
+            }
+            _ => panic!("expected solve"),
+        }
+        assert!(matches!(
+            Args::try_parse_from(["descramble", "index", "rebuild"])
+                .unwrap()
+                .command,
+            Command::Index {
+                action: IndexAction::Rebuild
+            }
+        ));
+        assert!(matches!(
+            Args::try_parse_from(["descramble", "dict", "check"])
+                .unwrap()
+                .command,
+            Command::Dict {
+                action: DictAction::Check
+            }
+        ));
+        assert!(Args::try_parse_from(["descramble", "solve", "abc", "--time-limit=-1"]).is_err());
+        assert!(Args::try_parse_from(["descramble", "index", "rebuild", "-w", "2"]).is_err());
+    }
     #[test]
     fn test_frequency_array() {
         let string = "AAAbbba";
@@ -154,7 +661,7 @@ mod test {
             [
                 4, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
             ],
-            Frequency::from(string.as_bytes()).arr
+            Frequency::from(string.as_bytes()).arr()
         );
     }
     #[test]
@@ -171,7 +678,78 @@ mod test {
     #[test]
     fn test_freq_fn() {
         let mut a = Frequency::from(1);
-        a.arr[0] = -1;
+        a.set(0, -1);
         assert_eq!(Frequency::is_valid(&a), false);
     }
+    #[test]
+    fn test_freq_lanes() {
+        let a = Frequency::from("zzy".as_bytes());
+        let b = Frequency::from("abz".as_bytes());
+        let diff = &a - &b;
+        assert_eq!(diff.get(0), -1);
+        assert_eq!(diff.get(1), -1);
+        assert_eq!(diff.get(24), 1);
+        assert_eq!(diff.get(25), 1);
+        assert!(!Frequency::is_valid(&diff));
+        assert_eq!(&diff + &b, a);
+        assert_eq!(diff.arr().iter().map(|val| *val as i32).sum::<i32>(), 0);
+    }
+    #[test]
+    fn test_from_json_errors() {
+        let source = Path::new("data.json");
+        let load =
+            |json: &str| Data::from_json(json.as_bytes(), source, &WordFilter::default()).err();
+        assert!(matches!(
+            load(r#"{"of": {"frequency": 0.1, "tag": "ADP"},}"#),
+            Some(DataError::Json(..))
+        ));
+        assert!(matches!(
+            load(r#"{"of": {"frequency": 0.1, "tag": "PREP"}}"#),
+            Some(DataError::UnknownTag { .. })
+        ));
+        assert!(matches!(
+            load(r#"{"b4": {"frequency": 0.1, "tag": "ADP"}}"#),
+            Some(DataError::NonAlphabetic { .. })
+        ));
+        assert!(matches!(
+            load(r#"{"of": {"frequency": -1, "tag": "ADP"}}"#),
+            Some(DataError::InvalidFrequency { .. })
+        ));
+        assert!(load(r#"{"Café": {"frequency": 0.1, "tag": "NOUN"}}"#).is_none());
+        assert!(load(r#"{"don't": {"frequency": 0.1, "tag": "AUX"}}"#).is_none());
+    }
+    #[test]
+    fn test_layers() {
+        let layer = |json: &str| Layer {
+            path: PathBuf::from("overlay.json"),
+            bytes: json.as_bytes().to_vec(),
+        };
+        let base = layer(
+            r#"{"statue": {"frequency": 2e-6, "tag": "NOUN"}, "astute": {"frequency": 1e-6, "tag": "ADJ"}}"#,
+        );
+        let overlay = layer(
+            r#"{"astute": {"remove": true}, "statue": {"frequency": 0.5}, "teats": {"frequency": 1e-7, "tag": "NOUN"}}"#,
+        );
+        let data = Data::from_layers(&[base, overlay], &WordFilter::default()).unwrap();
+        let key: Frequency = Frequency::from("statue".as_bytes());
+        let statue_group: GroupId = data
+            .index
+            .sub_multisets_of(&key)
+            .into_iter()
+            .find(|group| data.group_key(*group) == key)
+            .unwrap();
+        let group: Vec<&str> = data.group(statue_group).map(|id| data.word(id)).collect();
+        assert_eq!(group, ["statue"]);
+        let statue = data.group(statue_group).next().unwrap();
+        assert_eq!(data.frequency(statue), 0.5);
+        assert_eq!(data.tag(statue), tag_index("NOUN").unwrap());
+        assert_eq!(data.word_count(), 2);
+        assert!(matches!(
+            Data::from_layers(
+                &[layer(r#"{"statue": {"tag": "NOUN"}}"#)],
+                &WordFilter::default()
+            ),
+            Err(DataError::Incomplete { .. })
+        ));
+    }
 }
@@ -0,0 +1,439 @@
+use crate::data::{Data, DataError, Entry, Frequency, Layer, POS_TAGS, Tag, read_file, tag_index};
+use crate::error::AppError;
+use crate::words::WordFilter;
+use itertools::Itertools;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{read_dir, write};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Reads a word list into dictionary entries. Each line is a word, optionally followed by a
+/// tab-separated count and POS tag. Words without a count count once, and tags that are missing
+/// or unknown become `default_tag`. Frequencies are relative to the words that are kept, so they
+/// add up to 1 however many lines are dropped for not being usable.
+pub fn import(
+    bytes: &[u8],
+    source: &Path,
+    default_tag: &str,
+    filter: &WordFilter,
+) -> Result<BTreeMap<String, Entry>, DataError> {
+    let mut counts: BTreeMap<String, (f64, String)> = BTreeMap::new();
+    let mut total: f64 = 0.0;
+    for (number, line) in String::from_utf8_lossy(bytes).lines().enumerate() {
+        let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+        if fields[0].is_empty() || fields[0].starts_with('#') {
+            continue;
+        }
+        let count: f64 = match fields.get(1) {
+            Some(count) => match count.parse::<f64>() {
+                Ok(count) if count.is_finite() && count >= 0.0 => count,
+                _ => {
+                    return Err(DataError::Malformed {
+                        path: source.to_path_buf(),
+                        line: number + 1,
+                        reason: format!("\"{}\" isn't a count", count),
+                    });
+                }
+            },
+            None => 1.0,
+        };
+        let word: String = fields[0].to_lowercase();
+        if !filter.is_usable(&word) {
+            continue;
+        }
+        total += count;
+        let tag: String = fields
+            .get(2)
+            .map(|tag| tag.to_uppercase())
+            .filter(|tag| tag_index(tag).is_some())
+            .unwrap_or_else(|| default_tag.to_string());
+        // Repeated words add up, the first tag given sticks.
+        counts.entry(word).or_insert((0.0, tag)).0 += count;
+    }
+    Ok(counts
+        .into_iter()
+        .map(|(word, (count, tag))| {
+            let entry: Entry = Entry {
+                frequency: Some(if total > 0.0 { count / total } else { 0.0 }),
+                tag: Some(tag),
+                remove: false,
+            };
+            (word, entry)
+        })
+        .collect())
+}
+/// `dict import`: converts `input` and writes the dictionary to `output`, or stdout.
+pub fn import_file(
+    input: &Path,
+    output: Option<&Path>,
+    default_tag: &str,
+    filter: &WordFilter,
+) -> Result<(), DataError> {
+    let entries: BTreeMap<String, Entry> = import(&read_file(input)?, input, default_tag, filter)?;
+    write_dictionary(&entries, output)?;
+    eprintln!("Imported {} words.", entries.len());
+    Ok(())
+}
+/// Word counts of a corpus. `total` also counts the words that aren't usable in anagrams.
+#[derive(Debug, Default)]
+pub struct Corpus {
+    pub counts: HashMap<String, u64>,
+    pub total: u64,
+}
+impl Corpus {
+    /// Counts every file under `path`, or `path` itself if it's a file. Hidden files and
+    /// directories are skipped.
+    pub fn read(path: &Path, filter: &WordFilter) -> Result<Self, DataError> {
+        let mut corpus: Corpus = Corpus::default();
+        let mut pending: Vec<PathBuf> = vec![path.to_path_buf()];
+        while let Some(path) = pending.pop() {
+            if !path.is_dir() {
+                corpus.add(&String::from_utf8_lossy(&read_file(&path)?), filter);
+                continue;
+            }
+            let entries = read_dir(&path).map_err(|err| DataError::Io(path.clone(), err))?;
+            for entry in entries {
+                let entry = entry.map_err(|err| DataError::Io(path.clone(), err))?;
+                if !entry.file_name().to_string_lossy().starts_with('.') {
+                    pending.push(entry.path());
+                }
+            }
+        }
+        Ok(corpus)
+    }
+    /// Splits `text` into words on anything but letters and inner apostrophes.
+    pub fn add(&mut self, text: &str, filter: &WordFilter) {
+        for token in text.split(|char: char| !char.is_alphabetic() && char != '\'') {
+            let word: String = token.trim_matches('\'').to_lowercase();
+            if word.is_empty() {
+                continue;
+            }
+            self.total += 1;
+            if filter.is_usable(&word) {
+                *self.counts.entry(word).or_default() += 1;
+            }
+        }
+    }
+    /// Turns the counts into frequencies with add-`smoothing` smoothing over the words of the
+    /// corpus and of `base`, then blends in `base` with `weight` going to the corpus. Words new to
+    /// `base` get `default_tag`.
+    pub fn frequencies(
+        &self,
+        base: &BTreeMap<String, (f64, Tag)>,
+        weight: f64,
+        smoothing: f64,
+        default_tag: &str,
+    ) -> BTreeMap<String, Entry> {
+        let mut words: BTreeMap<&str, Option<(f64, Tag)>> = base
+            .iter()
+            .map(|(word, entry)| (word.as_str(), Some(*entry)))
+            .collect();
+        for word in self.counts.keys() {
+            words.entry(word.as_str()).or_insert(None);
+        }
+        let denominator: f64 = self.total as f64 + smoothing * words.len() as f64;
+        // Without a base to blend with, the corpus is all there is.
+        let weight: f64 = if base.is_empty() { 1.0 } else { weight };
+        words
+            .into_iter()
+            .map(|(word, entry)| {
+                let count: f64 = self.counts.get(word).copied().unwrap_or_default() as f64;
+                let corpus: f64 = if denominator > 0.0 {
+                    (count + smoothing) / denominator
+                } else {
+                    0.0
+                };
+                let (base, tag): (f64, String) = match entry {
+                    Some((frequency, tag)) => (frequency, POS_TAGS[tag as usize].to_string()),
+                    None => (0.0, default_tag.to_string()),
+                };
+                let entry: Entry = Entry {
+                    frequency: Some(weight * corpus + (1.0 - weight) * base),
+                    tag: Some(tag),
+                    remove: false,
+                };
+                (word.to_string(), entry)
+            })
+            .collect()
+    }
+}
+/// `dict from-corpus`: counts `corpus`, blends it with the `base` dictionary if there is one and
+/// writes the result to `output`, or stdout.
+pub fn from_corpus_file(
+    corpus: &Path,
+    output: Option<&Path>,
+    base: Option<&Path>,
+    weight: f64,
+    smoothing: f64,
+    default_tag: &str,
+    filter: &WordFilter,
+) -> Result<(), DataError> {
+    let counted: Corpus = Corpus::read(corpus, filter)?;
+    let base: BTreeMap<String, (f64, Tag)> = match base {
+        Some(base) => Data::merge_layers(&Data::read_layers(&[base.to_path_buf()])?)?,
+        None => BTreeMap::new(),
+    };
+    let entries: BTreeMap<String, Entry> =
+        counted.frequencies(&base, weight, smoothing, default_tag);
+    write_dictionary(&entries, output)?;
+    eprintln!(
+        "Counted {} words, {} distinct, into {} entries.",
+        counted.total,
+        counted.counts.len(),
+        entries.len()
+    );
+    Ok(())
+}
+/// How many of the largest anagram groups `dict check` lists.
+const LARGEST_GROUPS: usize = 10;
+
+/// What `dict check` found in a dictionary stack.
+#[derive(Debug, Default)]
+pub struct Check {
+    pub layers: usize,
+    pub entries: usize,
+    // Entries that would stop the dictionary from loading.
+    pub problems: Vec<DataError>,
+    pub warnings: Vec<String>,
+    // Words that load fine but can't be part of a solution, and why.
+    pub skipped: Vec<(String, &'static str)>,
+    pub tags: BTreeMap<Tag, usize>,
+    pub lengths: BTreeMap<usize, usize>,
+    // Largest first.
+    pub groups: Vec<Vec<String>>,
+    // Ascending.
+    pub frequencies: Vec<f64>,
+}
+impl Check {
+    /// Goes through every entry the way loading does, but keeps going past problems.
+    pub fn new(layers: &[Layer], filter: &WordFilter) -> Result<Self, DataError> {
+        let mut check: Check = Check {
+            layers: layers.len(),
+            ..Check::default()
+        };
+        let mut merged: BTreeMap<String, (f64, Tag)> = BTreeMap::new();
+        for layer in layers {
+            let entries: Vec<(String, Entry)> = Data::parse_layer(layer)?;
+            check.entries += entries.len();
+            let mut folded: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+            for (string, _) in entries.iter() {
+                folded
+                    .entry(string.to_lowercase())
+                    .or_default()
+                    .push(string);
+            }
+            for (_, strings) in folded.iter().filter(|(_, strings)| strings.len() > 1) {
+                check.warnings.push(format!(
+                    "{}: {} are the same word once lowercased",
+                    layer.path.display(),
+                    strings.join(", ")
+                ));
+            }
+            for (string, entry) in entries {
+                if entry.frequency == Some(0.0) {
+                    check.warnings.push(format!(
+                        "{}: \"{}\" has a frequency of 0 and can never rank",
+                        layer.path.display(),
+                        string
+                    ));
+                }
+                if let Err(err) = Data::apply_entry(&mut merged, &layer.path, string, entry) {
+                    check.problems.push(err);
+                }
+            }
+        }
+        let mut groups: HashMap<Frequency, Vec<String>> = HashMap::default();
+        for (string, (frequency, tag)) in merged {
+            if !filter.is_usable(&string) {
+                let reason: &str = match string.bytes().all(|char| char.is_ascii_lowercase()) {
+                    true => "too short",
+                    false => "not a-z",
+                };
+                check.skipped.push((string, reason));
+                continue;
+            }
+            *check.tags.entry(tag).or_default() += 1;
+            *check.lengths.entry(string.len()).or_default() += 1;
+            check.frequencies.push(frequency);
+            groups
+                .entry(Frequency::from(string.as_bytes()))
+                .or_default()
+                .push(string);
+        }
+        check.frequencies.sort_by(f64::total_cmp);
+        check.groups = groups
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .sorted_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)))
+            .take(LARGEST_GROUPS)
+            .collect();
+        Ok(check)
+    }
+    pub fn display(&self) {
+        println!(
+            "Checked {} entries in {} layer(s), {} usable words.",
+            self.entries,
+            self.layers,
+            self.frequencies.len()
+        );
+        if !self.problems.is_empty() {
+            println!("\nProblems ({}):", self.problems.len());
+            for problem in self.problems.iter() {
+                println!("  {}", problem);
+            }
+        }
+        if !self.warnings.is_empty() {
+            println!("\nWarnings ({}):", self.warnings.len());
+            for warning in self.warnings.iter() {
+                println!("  {}", warning);
+            }
+        }
+        if !self.skipped.is_empty() {
+            println!("\nSkipped ({}):", self.skipped.len());
+            for (string, reason) in self.skipped.iter() {
+                println!("  {:<20} {}", string, reason);
+            }
+        }
+        println!("\nWords per tag:");
+        for (tag, count) in self.tags.iter() {
+            println!("  {:<6} {}", POS_TAGS[*tag as usize], count);
+        }
+        println!("\nWords per length:");
+        for (length, count) in self.lengths.iter() {
+            println!("  {:<6} {}", length, count);
+        }
+        if !self.groups.is_empty() {
+            println!("\nLargest anagram groups:");
+            for group in self.groups.iter() {
+                println!("  {:<6} {}", group.len(), group.join(", "));
+            }
+        }
+        if let (Some(min), Some(max)) = (self.frequencies.first(), self.frequencies.last()) {
+            println!("\nFrequencies:");
+            println!(
+                "  min {:e}, median {:e}, max {:e}",
+                min,
+                self.frequencies[self.frequencies.len() / 2],
+                max
+            );
+            // Counts per power of ten, zeros on their own.
+            let mut decades: BTreeMap<i32, usize> = BTreeMap::new();
+            for frequency in self.frequencies.iter() {
+                let decade: i32 = match *frequency > 0.0 {
+                    true => frequency.log10().floor() as i32,
+                    false => i32::MIN,
+                };
+                *decades.entry(decade).or_default() += 1;
+            }
+            for (decade, count) in decades.iter().rev() {
+                match *decade {
+                    i32::MIN => println!("  {:<14} {}", "0", count),
+                    _ => println!(
+                        "  {:<14} {}",
+                        format!("1e{}..1e{}", decade, decade + 1),
+                        count
+                    ),
+                }
+            }
+        }
+    }
+}
+/// `dict check`: reports on the dictionary stack `sources`, failing if it wouldn't load.
+pub fn check_file(sources: &[PathBuf], filter: &WordFilter) -> Result<(), AppError> {
+    let check: Check = Check::new(&Data::read_layers(sources)?, filter)?;
+    check.display();
+    match check.problems.len() {
+        0 => Ok(()),
+        count => Err(AppError::CheckFailed(count)),
+    }
+}
+/// Writes dictionary entries as JSON, sorted by word.
+pub fn write_dictionary(
+    entries: &BTreeMap<String, Entry>,
+    output: Option<&Path>,
+) -> Result<(), DataError> {
+    // Serializing strings and numbers can't fail.
+    let mut bytes: Vec<u8> = serde_json::to_vec_pretty(entries).unwrap();
+    bytes.push(b'\n');
+    match output {
+        Some(path) => write(path, bytes).map_err(|err| DataError::Io(path.to_path_buf(), err)),
+        None => io::stdout()
+            .write_all(&bytes)
+            .map_err(|err| DataError::Io(PathBuf::from("<stdout>"), err)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data::Layer;
+    use crate::data::{DataError, tag_index};
+    use crate::dict::{Check, Corpus, import};
+    use crate::words::WordFilter;
+    use std::collections::BTreeMap;
+    use std::path::{Path, PathBuf};
+    #[test]
+    fn test_import() {
+        let filter = WordFilter::default();
+        let list = "Statue\t5\tnoun\nastute\t2\n# comment\nof\t2\tADP\nzq\t7\nstatue\t1\tVERB\n";
+        let entries = import(list.as_bytes(), Path::new("list.tsv"), "ADJ", &filter).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries["statue"].frequency, Some(0.6));
+        assert_eq!(entries["statue"].tag.as_deref(), Some("NOUN"));
+        assert_eq!(entries["astute"].tag.as_deref(), Some("ADJ"));
+        // The dropped "zq" doesn't count towards the total.
+        assert_eq!(entries["of"].frequency, Some(0.2));
+        let entries = import(b"statue\nastute\n", Path::new("list.txt"), "NOUN", &filter).unwrap();
+        assert_eq!(entries["astute"].frequency, Some(0.5));
+        assert!(matches!(
+            import(b"statue\tmany\n", Path::new("list.tsv"), "NOUN", &filter),
+            Err(DataError::Malformed { line: 1, .. })
+        ));
+    }
+    #[test]
+    fn test_corpus_frequencies() {
+        let mut corpus = Corpus::default();
+        corpus.add(
+            "The statue's base, and the STATUE.\n'Tis x2!",
+            &WordFilter::default(),
+        );
+        assert_eq!(corpus.total, 8);
+        assert_eq!(corpus.counts["statue"], 1);
+        assert_eq!(corpus.counts["the"], 2);
+        assert!(!corpus.counts.contains_key("statue's"));
+        let entries = corpus.frequencies(&BTreeMap::new(), 0.5, 0.0, "NOUN");
+        assert_eq!(entries["the"].frequency, Some(0.25));
+        let base = BTreeMap::from([(String::from("astute"), (0.5, tag_index("ADJ").unwrap()))]);
+        let entries = corpus.frequencies(&base, 0.5, 1.0, "NOUN");
+        // 8 words, plus one for each of the 6 distinct words in either.
+        assert_eq!(entries["astute"].frequency, Some(0.5 * 1.0 / 14.0 + 0.25));
+        assert_eq!(entries["astute"].tag.as_deref(), Some("ADJ"));
+        assert_eq!(entries["the"].frequency, Some(0.5 * 3.0 / 14.0));
+    }
+    #[test]
+    fn test_check() {
+        let layer = Layer {
+            path: PathBuf::from("data.json"),
+            bytes: br#"{
+                "statue": {"frequency": 2e-6, "tag": "NOUN"},
+                "Statue": {"frequency": 1e-6, "tag": "PROPN"},
+                "astute": {"frequency": 0, "tag": "ADJ"},
+                "b4": {"frequency": 1e-6, "tag": "ADP"},
+                "of": {"frequency": -1, "tag": "ADP"},
+                "teats": {"frequency": 1e-7, "tag": "PLURAL"},
+                "zz": {"frequency": 1e-7, "tag": "NOUN"}
+            }"#
+            .to_vec(),
+        };
+        let check = Check::new(&[layer], &WordFilter::default()).unwrap();
+        assert_eq!(check.entries, 7);
+        assert_eq!(check.problems.len(), 3);
+        assert_eq!(check.warnings.len(), 2);
+        assert_eq!(
+            check.skipped,
+            [("Statue".into(), "not a-z"), ("zz".into(), "too short")]
+        );
+        assert_eq!(check.groups, [vec!["astute", "statue"]]);
+        assert_eq!(check.tags[&tag_index("NOUN").unwrap()], 1);
+        assert_eq!(check.frequencies, [0.0, 2e-6]);
+    }
+}
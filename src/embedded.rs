@@ -0,0 +1,32 @@
+//! The default dictionary and templates compiled into the binary with the `embedded-data`
+//! feature. Without it there's nothing to fall back on and these return `None`.
+#[cfg(feature = "embedded-data")]
+static DICT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/data.json.gz"));
+#[cfg(feature = "embedded-data")]
+static TEMPLATES: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/templates.json.gz"));
+
+/// Stands in for a path in errors about the embedded data.
+pub const EMBEDDED_DIR: &str = "<embedded>";
+
+pub fn dict() -> Option<Vec<u8>> {
+    #[cfg(feature = "embedded-data")]
+    return Some(decompress(DICT));
+    #[cfg(not(feature = "embedded-data"))]
+    None
+}
+pub fn templates() -> Option<Vec<u8>> {
+    #[cfg(feature = "embedded-data")]
+    return Some(decompress(TEMPLATES));
+    #[cfg(not(feature = "embedded-data"))]
+    None
+}
+#[cfg(feature = "embedded-data")]
+fn decompress(bytes: &[u8]) -> Vec<u8> {
+    use std::io::Read;
+    let mut decompressed: Vec<u8> = vec![];
+    // Compressed by build.rs, so it can't be malformed.
+    flate2::read::GzDecoder::new(bytes)
+        .read_to_end(&mut decompressed)
+        .unwrap();
+    decompressed
+}
@@ -0,0 +1,71 @@
+use crate::data::DataError;
+use crate::words::LANGUAGES;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Why a command failed. Files that couldn't be loaded are a `DataError`, the rest are settings
+/// that were read fine but can't be used, or commands that ran and found a problem.
+#[derive(Debug)]
+pub enum AppError {
+    Data(DataError),
+    InvalidWeight {
+        path: PathBuf,
+        entry: String,
+        weight: f64,
+    },
+    UnknownLanguage {
+        path: PathBuf,
+        language: String,
+    },
+    CheckFailed(usize),
+    NoModel,
+}
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AppError::Data(err) => write!(f, "{}", err),
+            AppError::InvalidWeight {
+                path,
+                entry,
+                weight,
+            } => write!(
+                f,
+                "{}: template \"{}\" has weight {}, expected a number from 0 to 1",
+                path.display(),
+                entry,
+                weight
+            ),
+            AppError::UnknownLanguage { path, language } => write!(
+                f,
+                "{}: unknown language \"{}\", expected one of {}",
+                path.display(),
+                language,
+                LANGUAGES.join(", ")
+            ),
+            AppError::CheckFailed(count) => {
+                write!(
+                    f,
+                    "{} entries would stop the dictionary from loading",
+                    count
+                )
+            }
+            AppError::NoModel => write!(
+                f,
+                "the model scorer needs a language model, given with --model or found in the data directory"
+            ),
+        }
+    }
+}
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::Data(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+impl From<DataError> for AppError {
+    fn from(err: DataError) -> Self {
+        AppError::Data(err)
+    }
+}
@@ -0,0 +1,419 @@
+use crate::data::{POS_TAG_COUNT, Tag, tag_index};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// A pattern over POS tags, like `DET? ADJ* NOUN (VERB | AUX VERB)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Tag(Tag),
+    Sequence(Vec<Pattern>),
+    Alternation(Vec<Pattern>),
+    Optional(Box<Pattern>),
+    ZeroOrMore(Box<Pattern>),
+    OneOrMore(Box<Pattern>),
+}
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    Symbol(char),
+}
+impl Pattern {
+    /// Parses `text`, where names other than POS tags refer to `rules`.
+    pub fn parse(text: &str, rules: &HashMap<String, Pattern>) -> Result<Pattern, String> {
+        let tokens: Vec<Token> = Pattern::tokenize(text)?;
+        let mut pos: usize = 0;
+        let pattern: Pattern = Pattern::alternation(&tokens, &mut pos, rules)?;
+        match tokens.get(pos) {
+            None => Ok(pattern),
+            Some(Token::Symbol(symbol)) => Err(format!("unexpected \"{}\"", symbol)),
+            Some(Token::Name(name)) => Err(format!("unexpected \"{}\"", name)),
+        }
+    }
+    /// Parses a `NAME := pattern` rule. Rules can only refer to the ones before them, so they
+    /// can't recurse.
+    pub fn parse_rule(
+        text: &str,
+        rules: &HashMap<String, Pattern>,
+    ) -> Result<(String, Pattern), String> {
+        let (name, pattern): (&str, &str) = text
+            .split_once(":=")
+            .ok_or_else(|| String::from("expected \"NAME := pattern\""))?;
+        let name: &str = name.trim();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("\"{}\" isn't a valid rule name", name));
+        }
+        if tag_index(name).is_some() {
+            return Err(format!("\"{}\" is already a POS tag", name));
+        }
+        Ok((name.to_string(), Pattern::parse(pattern, rules)?))
+    }
+    fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+        let mut tokens: Vec<Token> = vec![];
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                c if c.is_whitespace() => {}
+                '(' | ')' | '|' | '?' | '*' | '+' => tokens.push(Token::Symbol(c)),
+                c if c.is_ascii_alphanumeric() || c == '_' => {
+                    let mut name: String = c.to_string();
+                    while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                        name.push(c);
+                    }
+                    tokens.push(Token::Name(name));
+                }
+                c => return Err(format!("unexpected \"{}\"", c)),
+            }
+        }
+        Ok(tokens)
+    }
+    fn alternation(
+        tokens: &[Token],
+        pos: &mut usize,
+        rules: &HashMap<String, Pattern>,
+    ) -> Result<Pattern, String> {
+        let mut alternatives: Vec<Pattern> = vec![Pattern::sequence(tokens, pos, rules)?];
+        while tokens.get(*pos) == Some(&Token::Symbol('|')) {
+            *pos += 1;
+            alternatives.push(Pattern::sequence(tokens, pos, rules)?);
+        }
+        Ok(match alternatives.len() {
+            1 => alternatives.pop().unwrap(),
+            _ => Pattern::Alternation(alternatives),
+        })
+    }
+    fn sequence(
+        tokens: &[Token],
+        pos: &mut usize,
+        rules: &HashMap<String, Pattern>,
+    ) -> Result<Pattern, String> {
+        let mut items: Vec<Pattern> = vec![];
+        loop {
+            let mut item: Pattern = match tokens.get(*pos) {
+                Some(Token::Name(name)) => match (tag_index(name), rules.get(name)) {
+                    (Some(tag), _) => Pattern::Tag(tag),
+                    (None, Some(rule)) => rule.clone(),
+                    (None, None) => return Err(format!("unknown tag or rule \"{}\"", name)),
+                },
+                Some(Token::Symbol('(')) => {
+                    *pos += 1;
+                    let group: Pattern = Pattern::alternation(tokens, pos, rules)?;
+                    if tokens.get(*pos) != Some(&Token::Symbol(')')) {
+                        return Err(String::from("missing \")\""));
+                    }
+                    group
+                }
+                _ => break,
+            };
+            *pos += 1;
+            while let Some(Token::Symbol(symbol @ ('?' | '*' | '+'))) = tokens.get(*pos) {
+                item = match symbol {
+                    '?' => Pattern::Optional(Box::new(item)),
+                    '*' => Pattern::ZeroOrMore(Box::new(item)),
+                    _ => Pattern::OneOrMore(Box::new(item)),
+                };
+                *pos += 1;
+            }
+            items.push(item);
+        }
+        match items.len() {
+            0 => Err(String::from("expected a tag, a rule or \"(\"")),
+            1 => Ok(items.pop().unwrap()),
+            _ => Ok(Pattern::Sequence(items)),
+        }
+    }
+}
+
+/// Nondeterministic automaton the patterns are first built into, one state per tag matched plus
+/// the glue between them.
+#[derive(Default)]
+struct Nfa {
+    epsilon: Vec<Vec<usize>>,
+    edges: Vec<Vec<(Tag, usize)>>,
+    // Weight and index of the best pattern ending in each state.
+    accept: Vec<Option<(f64, usize)>>,
+}
+impl Nfa {
+    fn state(&mut self) -> usize {
+        self.epsilon.push(vec![]);
+        self.edges.push(vec![]);
+        self.accept.push(None);
+        self.epsilon.len() - 1
+    }
+    /// Adds states matching `pattern` from `from`, returning the state it ends in. Loops only
+    /// ever go back to states made here, so `from` can be shared between patterns.
+    fn build(&mut self, pattern: &Pattern, from: usize) -> usize {
+        match pattern {
+            Pattern::Tag(tag) => {
+                let to: usize = self.state();
+                self.edges[from].push((*tag, to));
+                to
+            }
+            Pattern::Sequence(items) => items.iter().fold(from, |at, item| self.build(item, at)),
+            Pattern::Alternation(alternatives) => {
+                let to: usize = self.state();
+                for alternative in alternatives {
+                    let start: usize = self.state();
+                    self.epsilon[from].push(start);
+                    let end: usize = self.build(alternative, start);
+                    self.epsilon[end].push(to);
+                }
+                to
+            }
+            Pattern::Optional(item) => {
+                let start: usize = self.state();
+                self.epsilon[from].push(start);
+                let end: usize = self.build(item, start);
+                self.epsilon[start].push(end);
+                end
+            }
+            Pattern::ZeroOrMore(item) => {
+                let start: usize = self.state();
+                self.epsilon[from].push(start);
+                let end: usize = self.build(item, start);
+                self.epsilon[end].push(start);
+                start
+            }
+            Pattern::OneOrMore(item) => {
+                let start: usize = self.state();
+                self.epsilon[from].push(start);
+                let end: usize = self.build(item, start);
+                self.epsilon[end].push(start);
+                end
+            }
+        }
+    }
+    fn closure(&self, states: impl IntoIterator<Item = usize>) -> BTreeSet<usize> {
+        let mut closure: BTreeSet<usize> = BTreeSet::new();
+        let mut stack: Vec<usize> = states.into_iter().collect();
+        while let Some(state) = stack.pop() {
+            if closure.insert(state) {
+                stack.extend(self.epsilon[state].iter().copied());
+            }
+        }
+        closure
+    }
+}
+
+const NO_STATE: u32 = u32::MAX;
+
+/// Deterministic automaton over POS tags accepting every phrase shape the templates allow, with
+/// the weight and index of the best template each one matches.
+#[derive(Debug)]
+pub struct Automaton {
+    transitions: Vec<[u32; POS_TAG_COUNT]>,
+    accept: Vec<Option<(f64, usize)>>,
+    // Whether an accepting state can still be reached.
+    live: Vec<bool>,
+}
+impl Automaton {
+    /// Compiles weighted patterns. A phrase matching several of them takes the one with the
+    /// highest weight, the first of those on a tie.
+    pub fn new(patterns: &[(Pattern, f64)]) -> Self {
+        let mut nfa: Nfa = Nfa::default();
+        let start: usize = nfa.state();
+        for (index, (pattern, weight)) in patterns.iter().enumerate() {
+            let end: usize = nfa.build(pattern, start);
+            nfa.accept[end] = [nfa.accept[end], Some((*weight, index))]
+                .into_iter()
+                .flatten()
+                .reduce(best);
+        }
+        // Subset construction, each state standing for a set of NFA states.
+        let mut ids: HashMap<BTreeSet<usize>, u32> = HashMap::default();
+        let mut sets: Vec<BTreeSet<usize>> = vec![nfa.closure([start])];
+        ids.insert(sets[0].clone(), 0);
+        let mut transitions: Vec<[u32; POS_TAG_COUNT]> = vec![];
+        let mut accept: Vec<Option<(f64, usize)>> = vec![];
+        let mut i: usize = 0;
+        while i < sets.len() {
+            let mut row: [u32; POS_TAG_COUNT] = [NO_STATE; POS_TAG_COUNT];
+            for tag in 0..POS_TAG_COUNT as Tag {
+                let next: BTreeSet<usize> = nfa.closure(sets[i].iter().flat_map(|state| {
+                    nfa.edges[*state]
+                        .iter()
+                        .filter(move |(on, _)| *on == tag)
+                        .map(|(_, to)| *to)
+                }));
+                if next.is_empty() {
+                    continue;
+                }
+                row[tag as usize] = *ids.entry(next.clone()).or_insert_with(|| {
+                    sets.push(next);
+                    sets.len() as u32 - 1
+                });
+            }
+            transitions.push(row);
+            accept.push(
+                sets[i]
+                    .iter()
+                    .filter_map(|state| nfa.accept[*state])
+                    .reduce(best),
+            );
+            i += 1;
+        }
+        let mut live: Vec<bool> = accept.iter().map(Option::is_some).collect();
+        let mut changed: bool = true;
+        while changed {
+            changed = false;
+            for state in 0..transitions.len() {
+                if !live[state]
+                    && transitions[state]
+                        .iter()
+                        .any(|next| *next != NO_STATE && live[*next as usize])
+                {
+                    live[state] = true;
+                    changed = true;
+                }
+            }
+        }
+        Automaton {
+            transitions,
+            accept,
+            live,
+        }
+    }
+    /// Index and weight of the best template `tags` matches in this order.
+    pub fn matching(&self, tags: &[Tag]) -> Option<(usize, f64)> {
+        let mut state: u32 = 0;
+        for tag in tags {
+            state = self.transitions[state as usize][*tag as usize];
+            if state == NO_STATE {
+                return None;
+            }
+        }
+        self.accept[state as usize].map(|(weight, index)| (index, weight))
+    }
+    /// Every order of tags with `counts` of each that some template matches, with its weight,
+    /// highest weight first and stopping at `limit` of them. Repetition lets a pattern match a
+    /// great many orders of a long phrase, so each weight is searched for in turn, and the dead
+    /// ends that can't be completed with these counts are only tried once per weight.
+    pub fn orderings(&self, counts: &[u8; POS_TAG_COUNT], limit: usize) -> Vec<(Vec<Tag>, f64)> {
+        let mut weights: Vec<f64> = self
+            .accept
+            .iter()
+            .flatten()
+            .map(|(weight, _)| *weight)
+            .collect();
+        weights.sort_by(|a, b| b.total_cmp(a));
+        weights.dedup();
+        let mut orderings: Vec<(Vec<Tag>, f64)> = vec![];
+        for weight in weights {
+            if orderings.len() >= limit || !self.live[0] {
+                break;
+            }
+            let mut search: OrderingSearch = OrderingSearch {
+                counts: *counts,
+                tags: vec![],
+                orderings,
+                dead: HashSet::default(),
+                limit,
+                weight,
+            };
+            self.extend(0, &mut search);
+            orderings = search.orderings;
+        }
+        orderings
+    }
+    /// Extends `search.tags` from `state`, returning whether that led to any ordering.
+    fn extend(&self, state: u32, search: &mut OrderingSearch) -> bool {
+        if search.counts.iter().all(|count| *count == 0) {
+            return match self.accept[state as usize] {
+                Some((weight, _)) if weight == search.weight => {
+                    search.orderings.push((search.tags.clone(), weight));
+                    true
+                }
+                _ => false,
+            };
+        }
+        if search.dead.contains(&(state, search.counts)) {
+            return false;
+        }
+        let mut found: bool = false;
+        for tag in 0..POS_TAG_COUNT {
+            if search.orderings.len() >= search.limit {
+                return true;
+            }
+            let next: u32 = self.transitions[state as usize][tag];
+            if search.counts[tag] == 0 || next == NO_STATE || !self.live[next as usize] {
+                continue;
+            }
+            search.counts[tag] -= 1;
+            search.tags.push(tag as Tag);
+            found |= self.extend(next, search);
+            search.tags.pop();
+            search.counts[tag] += 1;
+        }
+        if !found {
+            search.dead.insert((state, search.counts));
+        }
+        found
+    }
+}
+/// The better of two matching patterns, as weight and index.
+fn best(a: (f64, usize), b: (f64, usize)) -> (f64, usize) {
+    match b.0 > a.0 || (b.0 == a.0 && b.1 < a.1) {
+        true => b,
+        false => a,
+    }
+}
+/// State of `Automaton::orderings` while it walks the automaton.
+struct OrderingSearch {
+    // Tags still to be placed.
+    counts: [u8; POS_TAG_COUNT],
+    tags: Vec<Tag>,
+    orderings: Vec<(Vec<Tag>, f64)>,
+    // States that can't be finished with the counts left.
+    dead: HashSet<(u32, [u8; POS_TAG_COUNT])>,
+    limit: usize,
+    // The only weight accepted in this pass.
+    weight: f64,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data::{POS_TAG_COUNT, Tag, tag_index};
+    use crate::grammar::{Automaton, Pattern};
+    use std::collections::HashMap;
+    fn tags(text: &str) -> Vec<Tag> {
+        text.split_whitespace()
+            .map(|tag| tag_index(tag).unwrap())
+            .collect()
+    }
+    #[test]
+    fn test_automaton() {
+        let mut rules: HashMap<String, Pattern> = HashMap::default();
+        let (name, np): (String, Pattern) =
+            Pattern::parse_rule("NP := DET? ADJ* NOUN", &rules).unwrap();
+        rules.insert(name, np);
+        let automaton: Automaton = Automaton::new(&[
+            (
+                Pattern::parse("NP (VERB | AUX VERB) NP", &rules).unwrap(),
+                1.0,
+            ),
+            (Pattern::parse("NOUN+", &rules).unwrap(), 0.5),
+            (Pattern::parse("NOUN NOUN", &rules).unwrap(), 0.8),
+        ]);
+        let matching = |text: &str| automaton.matching(&tags(text));
+        assert_eq!(matching("DET ADJ ADJ NOUN VERB NOUN"), Some((0, 1.0)));
+        assert_eq!(matching("NOUN AUX VERB DET NOUN"), Some((0, 1.0)));
+        assert_eq!(matching("NOUN NOUN"), Some((2, 0.8)));
+        assert_eq!(matching("NOUN NOUN NOUN"), Some((1, 0.5)));
+        assert_eq!(matching("VERB NOUN NOUN"), None);
+        assert_eq!(matching("DET NOUN"), None);
+        let mut counts: [u8; POS_TAG_COUNT] = [0; POS_TAG_COUNT];
+        for tag in tags("NOUN VERB ADJ NOUN") {
+            counts[tag as usize] += 1;
+        }
+        let mut orderings: Vec<(Vec<Tag>, f64)> = automaton.orderings(&counts, usize::MAX);
+        orderings.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut expected: Vec<(Vec<Tag>, f64)> = vec![
+            (tags("ADJ NOUN VERB NOUN"), 1.0),
+            (tags("NOUN VERB ADJ NOUN"), 1.0),
+        ];
+        expected.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(orderings, expected);
+        assert!(Pattern::parse("NP VERB", &HashMap::default()).is_err());
+        assert!(Pattern::parse("(NOUN VERB", &rules).is_err());
+        assert!(Pattern::parse("NOUN | ", &rules).is_err());
+        assert!(Pattern::parse_rule("NOUN := ADJ NOUN", &rules).is_err());
+    }
+}
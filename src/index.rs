@@ -0,0 +1,97 @@
+use crate::data::{ALPHA_COUNT, Frequency, GroupId};
+
+/// A dictionary frequency with its letter-presence mask precomputed.
+struct IndexEntry {
+    mask: u32,
+    frequency: Frequency,
+    group: GroupId,
+}
+
+/// Prefilter over the dictionary's frequencies. Entries are bucketed by length and carry a
+/// 26-bit letter-presence mask, so most candidates that can't fit a query are rejected with a
+/// single AND before any per-letter comparison.
+pub struct AnagramIndex {
+    // `buckets[n]` holds every frequency made up of `n` letters.
+    buckets: Vec<Vec<IndexEntry>>,
+}
+impl AnagramIndex {
+    /// Indexes the group keys, in group order.
+    pub fn new<'a>(frequencies: impl IntoIterator<Item = &'a Frequency>) -> Self {
+        let mut buckets: Vec<Vec<IndexEntry>> = vec![];
+        for (group, frequency) in frequencies.into_iter().enumerate() {
+            let length: usize = AnagramIndex::length(frequency);
+            if buckets.len() <= length {
+                buckets.resize_with(length + 1, Vec::new);
+            }
+            buckets[length].push(IndexEntry {
+                mask: AnagramIndex::mask(frequency),
+                frequency: *frequency,
+                group: group as GroupId,
+            });
+        }
+        AnagramIndex { buckets }
+    }
+    /// The group of every indexed frequency that fits within `frequency`.
+    pub fn sub_multisets_of(&self, frequency: &Frequency) -> Vec<GroupId> {
+        let mask: u32 = AnagramIndex::mask(frequency);
+        let length: usize =
+            AnagramIndex::length(frequency).min(self.buckets.len().saturating_sub(1));
+        let mut found: Vec<GroupId> = vec![];
+        for bucket in self.buckets.iter().take(length + 1).skip(1) {
+            for entry in bucket {
+                // Uses a letter the query doesn't have at all.
+                if entry.mask & !mask != 0 {
+                    continue;
+                }
+                if Frequency::is_valid(&(frequency - &entry.frequency)) {
+                    found.push(entry.group);
+                }
+            }
+        }
+        found
+    }
+    fn mask(frequency: &Frequency) -> u32 {
+        let mut mask: u32 = 0;
+        for letter in 0..ALPHA_COUNT {
+            if frequency.get(letter) > 0 {
+                mask |= 1 << letter;
+            }
+        }
+        mask
+    }
+    fn length(frequency: &Frequency) -> usize {
+        frequency
+            .arr()
+            .iter()
+            .map(|count| (*count).max(0) as usize)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data::Frequency;
+    use crate::index::AnagramIndex;
+    #[test]
+    fn test_sub_multisets() {
+        let words: Vec<Frequency> = ["tea", "eat", "tee", "cat", "a", "teat", "seat"]
+            .iter()
+            .map(|word| Frequency::from(word.as_bytes()))
+            .collect();
+        let index = AnagramIndex::new(&words);
+        let mut found: Vec<Frequency> = index
+            .sub_multisets_of(&Frequency::from("treat".as_bytes()))
+            .into_iter()
+            .map(|group| words[group as usize])
+            .collect();
+        found.sort();
+        found.dedup();
+        let mut expected = vec![
+            Frequency::from("tea".as_bytes()),
+            Frequency::from("a".as_bytes()),
+            Frequency::from("teat".as_bytes()),
+        ];
+        expected.sort();
+        assert_eq!(found, expected);
+    }
+}
@@ -1,11 +1,29 @@
 use application::Application;
 use std::env::current_exe;
+use std::process::exit;
 mod algorithm;
 mod application;
+mod cache;
 mod data;
+mod dict;
+mod embedded;
+mod error;
+mod grammar;
+mod index;
+mod ngram;
+mod paths;
+mod scorer;
+mod words;
 use clap::Parser;
-use data::{Args, Data, State};
-use std::path::{PathBuf};
+use algorithm::Templates;
+use data::{Args, Command, Data, DataError, DictAction, IndexAction, SolveArgs, State};
+use embedded::EMBEDDED_DIR;
+use error::AppError;
+use ngram::LanguageModel;
+use paths::{DICT_FILE, DataPaths, TEMPLATES_FILE};
+use scorer::{Scorer, ScorerSpec};
+use words::WordFilter;
+use std::path::{Path, PathBuf};
 
 /// DONE. No further changes.
 /// Entry point.
@@ -20,17 +38,94 @@ fn main() {
             );
         }
     };
-    let app: Application = Application::new(State::new(
-        Args::parse(),
-        match Data::try_from(&root_path.join("data")) {
-            Ok(data) => data,
-            Err(err) => panic!(
-                "Fatal error. Cannot retrieve data using Data::TryFrom.\n{:?}",
-                err
-            ),
-        },
-        root_path.to_path_buf(),
-    ));
-    app.start()
+    let args: Args = Args::parse();
+    if let Err(err) = run(args, root_path) {
+        eprintln!("error: {}", err);
+        exit(1);
+    }
+}
+/// Loads what the command needs and runs it, any loading error is reported once by `main`.
+fn run(args: Args, root_path: PathBuf) -> Result<(), AppError> {
+    let paths: DataPaths = DataPaths::new(&args, &root_path);
+    let filter: WordFilter = WordFilter::load(
+        paths.short_words().as_deref(),
+        args.language.as_deref(),
+        args.min_length,
+    )?;
+    match args.command {
+        Command::Solve(solve) => solve_anagram(solve, &paths, &filter)?,
+        Command::Index {
+            action: IndexAction::Rebuild,
+        } => {
+            let data: Data = Data::rebuild_index(&paths.dicts()?, &filter)?;
+            println!("Rebuilt dictionary index ({} words).", data.word_count());
+        }
+        Command::Dict {
+            action:
+                DictAction::Import {
+                    input,
+                    output,
+                    default_tag,
+                },
+        } => dict::import_file(&input, output.as_deref(), &default_tag, &filter)?,
+        Command::Dict {
+            action:
+                DictAction::FromCorpus {
+                    corpus,
+                    output,
+                    base,
+                    weight,
+                    smoothing,
+                    default_tag,
+                },
+        } => dict::from_corpus_file(
+            &corpus,
+            output.as_deref(),
+            base.as_deref(),
+            weight,
+            smoothing,
+            &default_tag,
+            &filter,
+        )?,
+        Command::Dict {
+            action: DictAction::Check,
+        } => dict::check_file(&paths.dicts()?, &filter)?,
+    }
+    Ok(())
+}
+/// Loads the dictionary, templates and language model, then solves the anagram.
+fn solve_anagram(args: SolveArgs, paths: &DataPaths, filter: &WordFilter) -> Result<(), AppError> {
+    let data: Data = load_data(paths, filter)?;
+    let templates: Templates = load_templates(paths)?;
+    let model: Option<LanguageModel> = paths
+        .model()
+        .map(|model| LanguageModel::load(&model))
+        .transpose()?;
+    let scorer: Box<dyn Scorer> = args
+        .scorer
+        .clone()
+        .unwrap_or_else(|| ScorerSpec::default_for(model.is_some()))
+        .build(model.is_some())?;
+    let app: Application = Application::new(State::new(args, data, templates, model, scorer));
+    app.start();
+    Ok(())
+}
+/// The dictionary that was found, or the embedded one if there's none.
+fn load_data(paths: &DataPaths, filter: &WordFilter) -> Result<Data, DataError> {
+    match (paths.dicts(), embedded::dict()) {
+        (Ok(dicts), _) => Data::load(&dicts, filter),
+        (Err(_), Some(bytes)) => {
+            Data::from_json(&bytes, &Path::new(EMBEDDED_DIR).join(DICT_FILE), filter)
+        }
+        (Err(err), None) => Err(err),
+    }
+}
+fn load_templates(paths: &DataPaths) -> Result<Templates, AppError> {
+    match (paths.templates(), embedded::templates()) {
+        (Ok(templates), _) => Templates::load(&templates),
+        (Err(_), Some(bytes)) => {
+            Templates::from_json(&bytes, &Path::new(EMBEDDED_DIR).join(TEMPLATES_FILE))
+        }
+        (Err(err), None) => Err(err.into()),
+    }
 }
-
@@ -0,0 +1,220 @@
+use crate::data::{Data, DataError, WordId, read_file};
+use std::collections::HashMap;
+use std::path::Path;
+
+pub const MODEL_FILES: [&str; 2] = ["model.arpa", "model.tsv"];
+/// Penalty for backing off to a shorter context in count files, as in stupid backoff.
+const COUNT_BACKOFF: f64 = 0.4;
+const SENTENCE_START: &str = "<s>";
+const SENTENCE_END: &str = "</s>";
+const UNKNOWN: &str = "<unk>";
+
+/// An n-gram language model. N-grams are keyed by their words joined with spaces and hold a
+/// log10 probability and a log10 backoff weight.
+#[derive(Debug, Default)]
+pub struct LanguageModel {
+    pub order: usize,
+    ngrams: HashMap<String, (f64, f64)>,
+}
+impl LanguageModel {
+    /// Reads an ARPA file, or anything else as `w1 [w2 ...]<TAB>count` lines.
+    pub fn load(source: &Path) -> Result<Self, DataError> {
+        let bytes: Vec<u8> = read_file(source)?;
+        let text: &str = &String::from_utf8_lossy(&bytes);
+        match text.trim_start().starts_with("\\data\\") {
+            true => LanguageModel::from_arpa(text, source),
+            false => LanguageModel::from_counts(text, source),
+        }
+    }
+    pub fn from_arpa(text: &str, source: &Path) -> Result<Self, DataError> {
+        let mut model: LanguageModel = LanguageModel::default();
+        // Which n-gram section the line is in, 0 outside of them.
+        let mut section: usize = 0;
+        for (number, line) in text.lines().enumerate() {
+            let line: &str = line.trim();
+            if let Some(n) = line
+                .strip_prefix('\\')
+                .and_then(|line| line.strip_suffix("-grams:"))
+            {
+                section = n.parse().map_err(|_| DataError::Malformed {
+                    path: source.to_path_buf(),
+                    line: number + 1,
+                    reason: format!("\"{}\" isn't an n-gram section", line),
+                })?;
+                model.order = model.order.max(section);
+                continue;
+            }
+            if line.starts_with('\\') {
+                section = 0;
+                continue;
+            }
+            if section == 0 || line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let malformed = |reason: &str| DataError::Malformed {
+                path: source.to_path_buf(),
+                line: number + 1,
+                reason: reason.to_string(),
+            };
+            if fields.len() != section + 1 && fields.len() != section + 2 {
+                return Err(malformed("wrong number of fields for its section"));
+            }
+            let parse = |field: &str| {
+                field
+                    .parse::<f64>()
+                    .map_err(|_| malformed("probabilities must be numbers"))
+            };
+            let log_prob: f64 = parse(fields[0])?;
+            let backoff: f64 = match fields.get(section + 1) {
+                Some(backoff) => parse(backoff)?,
+                None => 0.0,
+            };
+            model
+                .ngrams
+                .insert(fields[1..=section].join(" "), (log_prob, backoff));
+        }
+        Ok(model)
+    }
+    /// Relative counts of every n-gram among those sharing its context, backing off with a fixed
+    /// penalty.
+    pub fn from_counts(text: &str, source: &Path) -> Result<Self, DataError> {
+        let mut counts: HashMap<String, f64> = HashMap::default();
+        // Total count of the n-grams following each context, "" for unigrams.
+        let mut contexts: HashMap<String, f64> = HashMap::default();
+        let mut order: usize = 0;
+        for (number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (ngram, count): (&str, &str) = line.rsplit_once('\t').unwrap_or((line, ""));
+            let words: Vec<&str> = ngram.split_whitespace().collect();
+            let count: f64 = match count.trim().parse::<f64>() {
+                Ok(count) if count.is_finite() && count > 0.0 && !words.is_empty() => count,
+                _ => {
+                    return Err(DataError::Malformed {
+                        path: source.to_path_buf(),
+                        line: number + 1,
+                        reason: String::from("expected n-gram words, a tab and a count"),
+                    });
+                }
+            };
+            order = order.max(words.len());
+            *counts.entry(words.join(" ")).or_default() += count;
+            *contexts
+                .entry(words[..words.len() - 1].join(" "))
+                .or_default() += count;
+        }
+        let ngrams: HashMap<String, (f64, f64)> = counts
+            .into_iter()
+            .map(|(ngram, count)| {
+                let context: &str = ngram.rsplit_once(' ').map_or("", |(context, _)| context);
+                let log_prob: f64 = (count / contexts[context]).log10();
+                (ngram, (log_prob, COUNT_BACKOFF.log10()))
+            })
+            .collect();
+        Ok(LanguageModel { order, ngrams })
+    }
+    /// log10 probability of `word` following `history`, backing off to shorter histories. Words the
+    /// model has never seen get `<unk>` if it has one, otherwise `unseen`.
+    pub fn log_prob(&self, history: &[&str], word: &str, unseen: f64) -> f64 {
+        let history: &[&str] =
+            &history[history.len().saturating_sub(self.order.saturating_sub(1))..];
+        let mut backoff: f64 = 0.0;
+        for start in 0..=history.len() {
+            let context: String = history[start..].join(" ");
+            let ngram: String = match context.is_empty() {
+                true => word.to_string(),
+                false => format!("{} {}", context, word),
+            };
+            if let Some((log_prob, _)) = self.ngrams.get(&ngram) {
+                return backoff + log_prob;
+            }
+            // A context the model has never seen costs nothing to back off from.
+            backoff += self
+                .ngrams
+                .get(&context)
+                .map_or(0.0, |(_, backoff)| *backoff);
+        }
+        backoff
+            + self
+                .ngrams
+                .get(UNKNOWN)
+                .map_or(unseen, |(log_prob, _)| *log_prob)
+    }
+    /// Geometric mean of the probability of each word given the ones before it, so that phrases
+    /// of different lengths compare. Sentence boundaries count when the model knows them.
+    /// Unseen words fall back to their dictionary frequency.
+    pub fn score(&self, phrase: &[WordId], data: &Data) -> f64 {
+        let log_probs: Vec<(&str, f64)> = self.log_probs(phrase, data);
+        let total: f64 = log_probs.iter().map(|(_, log_prob)| log_prob).sum();
+        10f64.powf(total / log_probs.len().max(1) as f64)
+    }
+    /// log10 probability of each word of `phrase` given the ones before it, followed by that of
+    /// the sentence ending there if the model knows sentence boundaries.
+    pub fn log_probs<'a>(&self, phrase: &[WordId], data: &'a Data) -> Vec<(&'a str, f64)> {
+        let boundaries: bool = self.ngrams.contains_key(SENTENCE_START);
+        let mut words: Vec<&str> = vec![];
+        if boundaries {
+            words.push(SENTENCE_START);
+        }
+        let start: usize = words.len();
+        words.extend(phrase.iter().map(|id| data.word(*id)));
+        let mut log_probs: Vec<(&str, f64)> = vec![];
+        for (i, id) in phrase.iter().enumerate() {
+            let unseen: f64 = data.frequency(*id).max(f64::MIN_POSITIVE).log10();
+            let log_prob: f64 = self.log_prob(&words[..start + i], words[start + i], unseen);
+            log_probs.push((words[start + i], log_prob));
+        }
+        if boundaries {
+            let log_prob: f64 = self.log_prob(&words, SENTENCE_END, f64::MIN_POSITIVE.log10());
+            log_probs.push((SENTENCE_END, log_prob));
+        }
+        log_probs
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ngram::LanguageModel;
+    use std::path::Path;
+    const ARPA: &str = "
+\\data\\
+ngram 1=4
+ngram 2=2
+
+\\1-grams:
+-1.0 statue -0.3
+-1.0 of -0.2
+-1.5 liberty
+-2.0 astute
+
+\\2-grams:
+-0.2 statue of
+-0.1 of liberty
+
+\\end\\
+";
+    #[test]
+    fn test_arpa() {
+        let model = LanguageModel::from_arpa(ARPA, Path::new("model.arpa")).unwrap();
+        assert_eq!(model.order, 2);
+        assert_eq!(model.log_prob(&["statue"], "of", -9.0), -0.2);
+        // Backs off through the weight of "liberty", which has none.
+        assert_eq!(model.log_prob(&["liberty"], "of", -9.0), -1.0);
+        assert_eq!(model.log_prob(&["of"], "astute", -9.0), -0.2 - 2.0);
+        assert_eq!(model.log_prob(&[], "unknown", -9.0), -9.0);
+    }
+    #[test]
+    fn test_counts() {
+        let counts = "statue\t4\nof\t4\nstatue of\t3\nstatue in\t1\n";
+        let model = LanguageModel::from_counts(counts, Path::new("model.tsv")).unwrap();
+        assert_eq!(model.order, 2);
+        assert_eq!(model.log_prob(&["statue"], "of", -9.0), 0.75f64.log10());
+        assert_eq!(
+            model.log_prob(&["of"], "statue", -9.0),
+            0.4f64.log10() + 0.5f64.log10()
+        );
+        assert!(LanguageModel::from_counts("statue of\n", Path::new("model.tsv")).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use crate::data::{Args, Command, DataError};
+use crate::ngram::MODEL_FILES;
+use crate::words::SHORT_WORDS_FILE;
+use std::env::{split_paths, var_os};
+use std::path::{Path, PathBuf};
+
+pub const DICT_FILE: &str = "data.json";
+pub const TEMPLATES_FILE: &str = "templates.json";
+
+/// Where the dictionary and the templates are read from.
+#[derive(Debug, Clone)]
+pub struct DataPaths {
+    dicts: Vec<PathBuf>,
+    templates: Option<PathBuf>,
+    short_words: Option<PathBuf>,
+    model: Option<PathBuf>,
+    directories: Vec<PathBuf>,
+}
+impl DataPaths {
+    /// `--dict` and `--templates` win outright. Otherwise each file is looked up in the data
+    /// directory given by `--data-dir` or `DESCRAMBLE_DATA`, or failing that in `data/` next to
+    /// the binary and then in the XDG data directories.
+    pub fn new(args: &Args, exe_dir: &Path) -> Self {
+        DataPaths {
+            dicts: args.dict.clone(),
+            templates: args.templates.clone(),
+            short_words: args.short_words.clone(),
+            model: match &args.command {
+                Command::Solve(solve) => solve.model.clone(),
+                _ => None,
+            },
+            directories: match &args.data_dir {
+                Some(data_dir) => vec![data_dir.clone()],
+                None => DataPaths::default_directories(exe_dir),
+            },
+        }
+    }
+    /// The dictionary stack, base first. Every `--dict` is a layer, without any the `data.json`
+    /// that's found is the only one.
+    pub fn dicts(&self) -> Result<Vec<PathBuf>, DataError> {
+        match self.dicts.is_empty() {
+            true => Ok(vec![DataPaths::find(None, DICT_FILE, &self.directories)?]),
+            false => Ok(self.dicts.clone()),
+        }
+    }
+    pub fn templates(&self) -> Result<PathBuf, DataError> {
+        DataPaths::find(self.templates.as_ref(), TEMPLATES_FILE, &self.directories)
+    }
+    /// The short word settings, which are optional.
+    pub fn short_words(&self) -> Option<PathBuf> {
+        DataPaths::find(
+            self.short_words.as_ref(),
+            SHORT_WORDS_FILE,
+            &self.directories,
+        )
+        .ok()
+    }
+    /// The language model, which is optional. `model.arpa` wins over `model.tsv`.
+    pub fn model(&self) -> Option<PathBuf> {
+        if self.model.is_some() {
+            return self.model.clone();
+        }
+        MODEL_FILES
+            .iter()
+            .find_map(|file| DataPaths::find(None, file, &self.directories).ok())
+    }
+    fn find(
+        explicit: Option<&PathBuf>,
+        file: &str,
+        directories: &[PathBuf],
+    ) -> Result<PathBuf, DataError> {
+        if let Some(path) = explicit {
+            return Ok(path.clone());
+        }
+        let searched: Vec<PathBuf> = directories
+            .iter()
+            .map(|directory| directory.join(file))
+            .collect();
+        match searched.iter().find(|path| path.is_file()) {
+            Some(path) => Ok(path.clone()),
+            None => Err(DataError::NotFound {
+                file: file.to_string(),
+                searched,
+            }),
+        }
+    }
+    /// `data/` next to the binary, `$XDG_DATA_HOME/descramble` (`~/.local/share/descramble` when
+    /// unset), then `descramble/` in each of `$XDG_DATA_DIRS` (`/usr/local/share:/usr/share`).
+    fn default_directories(exe_dir: &Path) -> Vec<PathBuf> {
+        let mut directories: Vec<PathBuf> = vec![exe_dir.join("data")];
+        let data_home: Option<PathBuf> = match var_os("XDG_DATA_HOME") {
+            Some(path) if !path.is_empty() => Some(PathBuf::from(path)),
+            _ => var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")),
+        };
+        directories.extend(data_home.map(|path| path.join("descramble")));
+        let data_dirs: Vec<PathBuf> = match var_os("XDG_DATA_DIRS") {
+            Some(paths) if !paths.is_empty() => split_paths(&paths).collect(),
+            _ => vec![
+                PathBuf::from("/usr/local/share"),
+                PathBuf::from("/usr/share"),
+            ],
+        };
+        directories.extend(data_dirs.iter().map(|path| path.join("descramble")));
+        directories
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data::{Args, DataError};
+    use crate::paths::DataPaths;
+    use clap::Parser;
+    use std::path::{Path, PathBuf};
+    #[test]
+    fn test_resolve_explicit() {
+        let args = Args::parse_from([
+            "descramble",
+            "solve",
+            "abc",
+            "--data-dir",
+            "/nonexistent",
+            "--dict",
+            "words.json",
+        ]);
+        let paths = DataPaths::new(&args, Path::new("/"));
+        assert_eq!(paths.dicts().unwrap(), [PathBuf::from("words.json")]);
+        match paths.templates() {
+            Err(DataError::NotFound { file, searched }) => {
+                assert_eq!(file, "templates.json");
+                assert_eq!(searched, [PathBuf::from("/nonexistent/templates.json")]);
+            }
+            _ => panic!("templates.json shouldn't be found"),
+        }
+        let args = Args::parse_from([
+            "descramble",
+            "solve",
+            "abc",
+            "--dict",
+            "words.json",
+            "--templates",
+            "orders.json",
+        ]);
+        let paths = DataPaths::new(&args, Path::new("/"));
+        assert_eq!(paths.dicts().unwrap(), [PathBuf::from("words.json")]);
+        assert_eq!(paths.templates().unwrap(), PathBuf::from("orders.json"));
+    }
+}
@@ -0,0 +1,340 @@
+use crate::data::{Data, WordId};
+use crate::error::AppError;
+use crate::ngram::LanguageModel;
+
+pub const TEMPLATE_FIT_REWARD: f64 = 1.0;
+pub const TEMPLATE_UNFIT_PENALTY: f64 = 0.1;
+pub const SCORERS: [&str; 6] = ["mean", "geomean", "logprob", "min", "template", "model"];
+
+/// What a scorer gets to know about a phrase besides its words.
+pub struct PhraseContext<'a> {
+    pub data: &'a Data,
+    pub model: Option<&'a LanguageModel>,
+    // Weight of the template the phrase was reordered to fit, if any.
+    pub template_weight: Option<f64>,
+}
+
+/// Ranks phrases. Scores must be non-negative, since results are shown as their share of the
+/// total score.
+pub trait Scorer: Send + Sync {
+    /// Score of a word on its own.
+    fn word(&self, id: WordId, data: &Data) -> f64 {
+        data.frequency(id)
+    }
+    /// Score of a phrase in the order it'll be shown in.
+    fn phrase(&self, phrase: &[WordId], context: &PhraseContext) -> f64;
+    /// The most any phrase whose words have a mean frequency of at most `mean` can score, so that
+    /// phrases and partial solutions that can't make the results are skipped. `None` if there's
+    /// no such bound, which turns that off.
+    fn bound(&self, mean: f64) -> Option<f64>;
+    fn name(&self) -> &'static str;
+    /// Lines telling what went into the phrase's score, for `--explain`.
+    fn explain(&self, phrase: &[WordId], context: &PhraseContext) -> Vec<String> {
+        vec![format!(
+            "{}: {:.3e}",
+            self.name(),
+            self.phrase(phrase, context)
+        )]
+    }
+}
+
+/// Arithmetic mean of the word scores.
+pub struct Mean;
+impl Scorer for Mean {
+    fn name(&self) -> &'static str {
+        "mean"
+    }
+    fn phrase(&self, phrase: &[WordId], context: &PhraseContext) -> f64 {
+        let sum: f64 = phrase.iter().map(|id| self.word(*id, context.data)).sum();
+        sum / phrase.len().max(1) as f64
+    }
+    fn bound(&self, mean: f64) -> Option<f64> {
+        Some(mean)
+    }
+}
+/// Geometric mean of the word scores, which punishes a single rare word harder.
+pub struct GeometricMean;
+impl Scorer for GeometricMean {
+    fn name(&self) -> &'static str {
+        "geomean"
+    }
+    fn phrase(&self, phrase: &[WordId], context: &PhraseContext) -> f64 {
+        let sum: f64 = phrase
+            .iter()
+            .map(|id| self.word(*id, context.data).ln())
+            .sum();
+        (sum / phrase.len().max(1) as f64).exp()
+    }
+    // Never more than the arithmetic mean.
+    fn bound(&self, mean: f64) -> Option<f64> {
+        Some(mean)
+    }
+}
+/// Sum of the word scores' log10 probabilities, mapped from (-inf, 0] onto (0, 1] by
+/// `1 / (1 - sum)` so that it stays positive without underflowing and ranks phrases the same.
+/// Longer phrases score lower.
+pub struct LogProbability;
+impl LogProbability {
+    fn sum(&self, phrase: &[WordId], data: &Data) -> f64 {
+        phrase.iter().map(|id| self.word(*id, data).log10()).sum()
+    }
+}
+impl Scorer for LogProbability {
+    fn name(&self) -> &'static str {
+        "logprob"
+    }
+    fn phrase(&self, phrase: &[WordId], context: &PhraseContext) -> f64 {
+        1.0 / (1.0 - self.sum(phrase, context.data))
+    }
+    // The mean of the logs is at most the log of the mean, so with frequencies at most 1 the sum
+    // is at most `log10(mean)`.
+    fn bound(&self, mean: f64) -> Option<f64> {
+        Some(1.0 / (1.0 - mean.min(1.0).log10()))
+    }
+    fn explain(&self, phrase: &[WordId], context: &PhraseContext) -> Vec<String> {
+        vec![format!(
+            "{}: {:.3e} (log10 sum {:.3})",
+            self.name(),
+            self.phrase(phrase, context),
+            self.sum(phrase, context.data)
+        )]
+    }
+}
+/// Score of the rarest word.
+pub struct MinFrequency;
+impl Scorer for MinFrequency {
+    fn name(&self) -> &'static str {
+        "min"
+    }
+    fn phrase(&self, phrase: &[WordId], context: &PhraseContext) -> f64 {
+        phrase
+            .iter()
+            .map(|id| self.word(*id, context.data))
+            .fold(f64::INFINITY, f64::min)
+            .min(1.0)
+    }
+    fn bound(&self, mean: f64) -> Option<f64> {
+        Some(mean)
+    }
+}
+/// A reward for phrases that fit a template and a penalty for those that don't, meant to be
+/// combined with another scorer. A template's weight moves its reward from the penalty at 0 to the
+/// full reward at 1, so fitting any template never scores below fitting none.
+pub struct TemplateFit;
+impl Scorer for TemplateFit {
+    fn name(&self) -> &'static str {
+        "template"
+    }
+    fn phrase(&self, _: &[WordId], context: &PhraseContext) -> f64 {
+        match context.template_weight {
+            Some(weight) => {
+                TEMPLATE_UNFIT_PENALTY + (TEMPLATE_FIT_REWARD - TEMPLATE_UNFIT_PENALTY) * weight
+            }
+            None => TEMPLATE_UNFIT_PENALTY,
+        }
+    }
+    // Template weights are at most 1.
+    fn bound(&self, _: f64) -> Option<f64> {
+        Some(TEMPLATE_FIT_REWARD.max(TEMPLATE_UNFIT_PENALTY))
+    }
+}
+/// Probability of the phrase under the language model, per word.
+pub struct ModelScore;
+impl Scorer for ModelScore {
+    fn name(&self) -> &'static str {
+        "model"
+    }
+    fn explain(&self, phrase: &[WordId], context: &PhraseContext) -> Vec<String> {
+        let mut lines: Vec<String> = vec![format!(
+            "{}: {:.3e}",
+            self.name(),
+            self.phrase(phrase, context)
+        )];
+        if let Some(model) = context.model {
+            for (word, log_prob) in model.log_probs(phrase, context.data) {
+                lines.push(format!("  {}: log10 p = {:.3}", word, log_prob));
+            }
+        }
+        lines
+    }
+    fn phrase(&self, phrase: &[WordId], context: &PhraseContext) -> f64 {
+        context
+            .model
+            .map_or(0.0, |model| model.score(phrase, context.data))
+    }
+    // Word order changes the score, word frequencies don't limit it.
+    fn bound(&self, _: f64) -> Option<f64> {
+        None
+    }
+}
+/// Product of other scorers' scores, each raised to its weight. A weight of 1 takes a score as is
+/// and 0 ignores it.
+pub struct Weighted {
+    pub parts: Vec<(Box<dyn Scorer>, f64)>,
+}
+impl Scorer for Weighted {
+    fn name(&self) -> &'static str {
+        "weighted"
+    }
+    // Each part's own explanation, with its weight.
+    fn explain(&self, phrase: &[WordId], context: &PhraseContext) -> Vec<String> {
+        let mut lines: Vec<String> = vec![];
+        for (scorer, weight) in self.parts.iter() {
+            let mut explanation: Vec<String> = scorer.explain(phrase, context);
+            if *weight != 1.0 && !explanation.is_empty() {
+                explanation[0] += &format!(" ^ {}", weight);
+            }
+            lines.extend(explanation);
+        }
+        lines
+    }
+    fn phrase(&self, phrase: &[WordId], context: &PhraseContext) -> f64 {
+        self.parts
+            .iter()
+            .map(|(scorer, weight)| weigh(scorer.phrase(phrase, context), *weight))
+            .product()
+    }
+    fn bound(&self, mean: f64) -> Option<f64> {
+        self.parts
+            .iter()
+            .map(|(scorer, weight)| scorer.bound(mean).map(|bound| weigh(bound, *weight)))
+            .product()
+    }
+}
+fn weigh(score: f64, weight: f64) -> f64 {
+    match weight {
+        1.0 => score,
+        _ => score.powf(weight),
+    }
+}
+
+/// The scorers a `--scorer` value can name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScorerKind {
+    Mean,
+    GeometricMean,
+    LogProbability,
+    MinFrequency,
+    Template,
+    Model,
+}
+impl ScorerKind {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mean" => Some(ScorerKind::Mean),
+            "geomean" => Some(ScorerKind::GeometricMean),
+            "logprob" => Some(ScorerKind::LogProbability),
+            "min" => Some(ScorerKind::MinFrequency),
+            "template" => Some(ScorerKind::Template),
+            "model" => Some(ScorerKind::Model),
+            _ => None,
+        }
+    }
+}
+/// A `--scorer` value: scorer names with optional weights, like `mean,template:0.5`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScorerSpec(pub Vec<(ScorerKind, f64)>);
+impl ScorerSpec {
+    /// `model,template` when there's a language model, `mean,template` otherwise.
+    pub fn default_for(model: bool) -> Self {
+        let first: ScorerKind = if model {
+            ScorerKind::Model
+        } else {
+            ScorerKind::Mean
+        };
+        ScorerSpec(vec![(first, 1.0), (ScorerKind::Template, 1.0)])
+    }
+    pub fn build(&self, model: bool) -> Result<Box<dyn Scorer>, AppError> {
+        let mut parts: Vec<(Box<dyn Scorer>, f64)> = vec![];
+        for (kind, weight) in self.0.iter() {
+            let scorer: Box<dyn Scorer> = match kind {
+                ScorerKind::Mean => Box::new(Mean),
+                ScorerKind::GeometricMean => Box::new(GeometricMean),
+                ScorerKind::LogProbability => Box::new(LogProbability),
+                ScorerKind::MinFrequency => Box::new(MinFrequency),
+                ScorerKind::Template => Box::new(TemplateFit),
+                ScorerKind::Model if model => Box::new(ModelScore),
+                ScorerKind::Model => return Err(AppError::NoModel),
+            };
+            parts.push((scorer, *weight));
+        }
+        Ok(Box::new(Weighted { parts }))
+    }
+}
+pub fn parse_spec(value: &str) -> Result<ScorerSpec, String> {
+    let mut parts: Vec<(ScorerKind, f64)> = vec![];
+    for part in value.split(',').map(str::trim) {
+        let (name, weight): (&str, &str) = part.split_once(':').unwrap_or((part, "1"));
+        let Some(kind) = ScorerKind::from_name(name) else {
+            return Err(format!(
+                "unknown scorer \"{}\", expected one of {}",
+                name,
+                SCORERS.join(", ")
+            ));
+        };
+        match weight.parse::<f64>() {
+            Ok(weight) if weight.is_finite() && weight >= 0.0 => parts.push((kind, weight)),
+            _ => return Err(format!("\"{}\" isn't a weight no less than 0", weight)),
+        }
+    }
+    Ok(ScorerSpec(parts))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data::{Data, WordId};
+    use crate::scorer::{PhraseContext, ScorerSpec, parse_spec};
+    use crate::words::WordFilter;
+    use std::path::Path;
+    #[test]
+    fn test_scorers() {
+        let json = br#"{
+            "statue": {"frequency": 0.04, "tag": "NOUN"},
+            "of": {"frequency": 0.25, "tag": "ADP"}
+        }"#;
+        let data = Data::from_json(json, Path::new("data.json"), &WordFilter::default()).unwrap();
+        let phrase: Vec<WordId> = vec![1, 0];
+        let context = |template_weight| PhraseContext {
+            data: &data,
+            model: None,
+            template_weight,
+        };
+        let score = |spec: &str, template_weight| {
+            parse_spec(spec)
+                .unwrap()
+                .build(false)
+                .unwrap()
+                .phrase(&phrase, &context(template_weight))
+        };
+        assert_eq!(score("mean", Some(1.0)), 0.145);
+        assert!((score("geomean", Some(1.0)) - 0.1).abs() < 1e-12);
+        // log10(0.04 * 0.25) = -2
+        assert!((score("logprob", Some(1.0)) - 1.0 / 3.0).abs() < 1e-12);
+        assert_eq!(score("min", Some(1.0)), 0.04);
+        assert_eq!(score("mean,template", None), 0.145 * 0.1);
+        assert_eq!(score("mean:2,template:0", None), 0.145 * 0.145);
+        // Fitting a light template still beats fitting none.
+        assert_eq!(score("template", Some(0.0)), score("template", None));
+        assert!(score("template", Some(0.05)) > score("template", None));
+        assert!((score("template", Some(0.5)) - 0.55).abs() < 1e-12);
+        assert_eq!(
+            parse_spec("mean, template:1").unwrap(),
+            ScorerSpec::default_for(false)
+        );
+        let explanation: Vec<String> = parse_spec("mean:2,template")
+            .unwrap()
+            .build(false)
+            .unwrap()
+            .explain(&phrase, &context(None));
+        assert_eq!(explanation, ["mean: 1.450e-1 ^ 2", "template: 1.000e-1"]);
+        assert!(parse_spec("median").is_err());
+        assert!(parse_spec("mean:-1").is_err());
+        assert!(parse_spec("model").unwrap().build(false).is_err());
+        let bound = parse_spec("mean,template").unwrap().build(false).unwrap();
+        assert_eq!(bound.bound(0.5), Some(0.5));
+        let logprob = parse_spec("logprob").unwrap().build(false).unwrap();
+        assert!(logprob.phrase(&phrase, &context(None)) <= logprob.bound(0.145).unwrap());
+        let unbounded = parse_spec("model,template").unwrap().build(true).unwrap();
+        assert_eq!(unbounded.bound(0.5), None);
+    }
+}
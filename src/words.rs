@@ -0,0 +1,143 @@
+use crate::cache::fnv1a;
+use crate::data::{DataError, read_file};
+use crate::error::AppError;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+pub const SHORT_WORDS_FILE: &str = "short_words.json";
+pub const LANGUAGES: [&str; 4] = ["en", "de", "es", "fr"];
+const DEFAULT_MIN_LENGTH: usize = 3;
+
+/// Words shorter than the minimum length that are kept anyway, the rest are mostly
+/// abbreviations. Only the a-z ones matter, anagrams don't use any other letters.
+fn default_short_words(language: &str) -> &'static [&'static str] {
+    match language {
+        "de" => &[
+            "ab", "am", "an", "da", "du", "er", "es", "im", "in", "ja", "ob", "so", "um", "wo",
+            "zu",
+        ],
+        "es" => &[
+            "a", "e", "o", "u", "y", "al", "da", "de", "el", "en", "es", "ha", "la", "le", "lo",
+            "me", "mi", "no", "se", "si", "su", "te", "tu", "un", "va", "ya", "yo",
+        ],
+        "fr" => &[
+            "a", "y", "au", "ce", "de", "du", "en", "et", "il", "je", "la", "le", "ma", "me", "ne",
+            "ni", "on", "ou", "sa", "se", "si", "ta", "te", "tu", "un", "va", "vu",
+        ],
+        _ => &[
+            "a", "i", "am", "an", "as", "at", "be", "by", "do", "he", "hi", "if", "in", "is", "it",
+            "me", "my", "no", "of", "oh", "on", "or", "ox", "so", "to", "up", "us",
+        ],
+    }
+}
+/// `short_words.json`. Every field is optional, `allow` and `forbid` adjust the language's
+/// whitelist.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ShortWordsFile {
+    language: Option<String>,
+    min_length: Option<usize>,
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default)]
+    forbid: Vec<String>,
+}
+
+/// Decides which dictionary words can be part of a solution.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WordFilter {
+    pub min_length: usize,
+    pub short_words: BTreeSet<String>,
+}
+impl Default for WordFilter {
+    fn default() -> Self {
+        WordFilter::for_language("en")
+    }
+}
+impl WordFilter {
+    pub fn for_language(language: &str) -> Self {
+        WordFilter {
+            min_length: DEFAULT_MIN_LENGTH,
+            short_words: default_short_words(language)
+                .iter()
+                .map(|word| word.to_string())
+                .collect(),
+        }
+    }
+    /// Starts from the defaults of the language, then applies `source` if there is one, then
+    /// `--language` and `--min-length`, which win over the file.
+    pub fn load(
+        source: Option<&Path>,
+        language: Option<&str>,
+        min_length: Option<usize>,
+    ) -> Result<Self, AppError> {
+        let file: ShortWordsFile = match source {
+            Some(source) => serde_json::from_slice(&read_file(source)?)
+                .map_err(|err| DataError::Json(source.to_path_buf(), err))?,
+            None => ShortWordsFile::default(),
+        };
+        let language: &str = language.or(file.language.as_deref()).unwrap_or("en");
+        if !LANGUAGES.contains(&language) {
+            return Err(AppError::UnknownLanguage {
+                path: source.map(Path::to_path_buf).unwrap_or_default(),
+                language: language.to_string(),
+            });
+        }
+        let mut filter: WordFilter = WordFilter::for_language(language);
+        filter.min_length = min_length.or(file.min_length).unwrap_or(filter.min_length);
+        for word in file.allow {
+            filter.short_words.insert(word.to_lowercase());
+        }
+        for word in file.forbid {
+            filter.short_words.remove(&word.to_lowercase());
+        }
+        Ok(filter)
+    }
+    /// Whether `word` can be part of a solution. Proper nouns, contractions and words outside
+    /// a-z are real words, just not ones anagrams use.
+    pub fn is_usable(&self, word: &str) -> bool {
+        word.bytes().all(|char| char.is_ascii_lowercase())
+            && (word.len() >= self.min_length || self.short_words.contains(word))
+    }
+    /// Changes whenever the filter does, so that indexes built with another one are rebuilt.
+    pub fn fingerprint(&self) -> u64 {
+        let mut bytes: Vec<u8> = self.min_length.to_le_bytes().to_vec();
+        for word in self.short_words.iter() {
+            bytes.extend(word.as_bytes());
+            bytes.push(0);
+        }
+        fnv1a(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::words::WordFilter;
+    use std::fs::write;
+    #[test]
+    fn test_word_filter() {
+        let filter = WordFilter::default();
+        assert!(filter.is_usable("ox"));
+        assert!(!filter.is_usable("ok"));
+        assert!(!filter.is_usable("Ox"));
+        let path = std::env::temp_dir().join(format!("short-words-{}.json", std::process::id()));
+        write(
+            &path,
+            r#"{"allow": ["ok", "pi"], "forbid": ["ox"], "min_length": 4}"#,
+        )
+        .unwrap();
+        let custom = WordFilter::load(Some(&path), None, None).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(custom.is_usable("ok"));
+        assert!(!custom.is_usable("ox"));
+        assert!(!custom.is_usable("the"));
+        assert!(custom.is_usable("them"));
+        assert_ne!(custom.fingerprint(), filter.fingerprint());
+        let german = WordFilter::load(None, Some("de"), Some(2)).unwrap();
+        assert!(german.is_usable("ox"));
+        assert!(!german.is_usable("a"));
+        assert!(german.is_usable("zu"));
+        assert!(WordFilter::load(None, Some("xx"), None).is_err());
+    }
+}